@@ -0,0 +1,112 @@
+//! Processing of the paths passed to an operation.
+use crate::{
+    error::{Context, Error, Result},
+    guard::Guard,
+};
+use std::path::{Path, PathBuf};
+
+/// What an operation does when one of the paths passed to it fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Stop at the first failed path and return its error, the paths after
+    /// it are left untouched.
+    #[default]
+    Strict,
+    /// Process every path and return [`Error::Batch`] with a [`BatchReport`]
+    /// if some of them failed. A cancelled operation still stops right away.
+    Continue,
+}
+
+/// What happened to every path passed to an operation in
+/// [`ErrorMode::Continue`], see [`Error::batch`](crate::Error::batch).
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    results: Vec<(PathBuf, Option<Error>)>,
+}
+
+impl BatchReport {
+    /// Returns the paths processed successfully, in the order they were
+    /// passed.
+    pub fn succeeded(&self) -> impl Iterator<Item = &Path> {
+        self.results
+            .iter()
+            .filter(|(_, err)| err.is_none())
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Returns the failed paths with their errors, in the order they were
+    /// passed.
+    pub fn failed(&self) -> impl Iterator<Item = (&Path, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(path, err)| err.as_ref().map(|err| (path.as_path(), err)))
+    }
+
+    /// Returns the error of the `path`, or `None` if it was processed
+    /// successfully or wasn't passed.
+    pub fn error(&self, path: &Path) -> Option<&Error> {
+        self.failed().find(|(p, _)| *p == path).map(|(_, err)| err)
+    }
+}
+
+/// Calls `f` for every path, stopping at the first error or collecting the
+/// errors into a [`BatchReport`] depending on the `mode`.
+///
+/// The report is returned only with [`Error::Batch`]: if nothing failed,
+/// every path succeeded and the report would only repeat the input, so the
+/// operations keep returning `()`.
+pub(crate) fn for_each<F>(
+    op: &'static str,
+    paths: &[PathBuf],
+    mode: ErrorMode,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<()>,
+{
+    let mut report = BatchReport::default();
+    for path in paths {
+        match f(path) {
+            Err(e) if mode == ErrorMode::Strict || matches!(e, Error::Cancelled(_)) => {
+                return Err(e)
+            }
+            res => report.results.push((path.clone(), res.err())),
+        }
+    }
+
+    if report.failed().next().is_none() {
+        return Ok(());
+    }
+    let mut ctx = Context::new(op, None, None);
+    ctx.set_batch(report);
+    Err(Error::Batch(ctx))
+}
+
+/// Same as [`for_each`], but checks the paths with the `guard`. In
+/// [`ErrorMode::Strict`] all the paths are checked before the first one is
+/// processed, so nothing is done if one of them is protected. In
+/// [`ErrorMode::Continue`] every path is checked when it's reached, and a
+/// protected path is recorded in the report like any other failure.
+pub(crate) fn for_each_guarded<F>(
+    op: &'static str,
+    paths: &[PathBuf],
+    mode: ErrorMode,
+    guard: &Guard,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<()>,
+{
+    if mode == ErrorMode::Strict {
+        for path in paths {
+            guard.check(op, path)?;
+        }
+    }
+
+    for_each(op, paths, mode, |path| {
+        if mode == ErrorMode::Continue {
+            guard.check(op, path)?;
+        }
+        f(path)
+    })
+}
@@ -0,0 +1,54 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A token to stop a running operation from another thread.
+///
+/// Clones of the token share the state, so one clone is passed to the
+/// operation options and another one is cancelled, e.g. when the user hits
+/// "Cancel". The operation checks the token between entries and between
+/// chunks of large files, and returns
+/// [`Error::Cancelled`](crate::Error::Cancelled).
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a token that isn't cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Requests the operations using the token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if [`cancel`](CancelToken::cancel) was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// What a cancelled operation has completed, see
+/// [`Error::summary`](crate::Error::summary).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub(crate) entries: u64,
+    pub(crate) bytes: u64,
+}
+
+impl Summary {
+    /// Number of entries processed completely.
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Number of bytes copied, including the bytes of the file that was
+    /// being copied.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
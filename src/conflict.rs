@@ -0,0 +1,166 @@
+use std::{
+    fmt,
+    fs::{File, Metadata},
+    io::{self, BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+type Callback = Arc<dyn Fn(&Path, &Path) -> ConflictAction + Send + Sync>;
+
+/// What to do with an entry whose destination already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictAction {
+    /// Replace the destination once the entry is complete, so a failed copy
+    /// keeps it. Only an entry of the same kind is replaced: a directory
+    /// replaces a directory, a file or a symlink replaces a file or a
+    /// symlink. Overwriting an entry of another kind or the entry itself
+    /// fails with [`Error::DestinationExists`](crate::Error::DestinationExists).
+    ///
+    /// The destination is replaced by a new entry, not written in place: a
+    /// symlink is replaced instead of written through, a file with other hard
+    /// links gets a new inode and the links keep the old content, and the
+    /// owner, permissions and extended attributes of the destination are not
+    /// kept (see [`Preserve`](crate::Preserve) for those of the entry).
+    Overwrite,
+    /// Leave the destination as it is and don't copy the entry. A skipped
+    /// directory is skipped with its whole content.
+    Skip,
+    /// Copy the entry next to the destination with a numbered suffix,
+    /// e.g. `file (2).txt`.
+    Rename,
+    /// Copy the content of the directory into the existing directory.
+    /// Only valid if both the entry and the destination are directories.
+    Merge,
+    /// Stop with [`Error::DestinationExists`](crate::Error::DestinationExists).
+    Error,
+}
+
+/// Policy for entries whose destination already exists, used by
+/// [`cp_with`](crate::cp_with), [`mv_with`](crate::mv_with) and
+/// [`extract_with`](crate::extract_with).
+///
+/// The policy is applied to every entry of the tree, files and directories
+/// alike. Unless stated otherwise, an existing directory is merged with the
+/// copied one, and the policy is then applied to its content. A file is
+/// never overwritten by a directory or the other way round, see
+/// [`ConflictAction::Overwrite`].
+#[derive(Clone, Default)]
+pub enum ConflictPolicy {
+    /// Overwrite existing files, merge existing directories. The default.
+    #[default]
+    Overwrite,
+    /// Keep existing files untouched, merge existing directories.
+    Skip,
+    /// Stop on the first existing file or directory.
+    Error,
+    /// Overwrite existing files only if the entry was modified later,
+    /// merge existing directories.
+    OverwriteIfNewer,
+    /// Overwrite existing files only if their size or content differs,
+    /// merge existing directories.
+    OverwriteIfDifferent,
+    /// Copy files and directories next to the existing ones with a numbered
+    /// suffix, e.g. `file (2).txt`.
+    RenameWithSuffix,
+    /// Merge existing directories, stop on the first existing file.
+    MergeDirectories,
+    /// Ask the callback, which gets the entry path and the destination path.
+    Custom(Callback),
+}
+
+impl ConflictPolicy {
+    /// Creates a [`ConflictPolicy::Custom`] policy.
+    pub fn custom<F>(callback: F) -> ConflictPolicy
+    where
+        F: Fn(&Path, &Path) -> ConflictAction + Send + Sync + 'static,
+    {
+        ConflictPolicy::Custom(Arc::new(callback))
+    }
+
+    /// Decides what to do with the `from` entry, whose destination `to`
+    /// already exists.
+    pub(crate) fn resolve(
+        &self,
+        from: &Path,
+        from_meta: &Metadata,
+        to: &Path,
+        to_meta: &Metadata,
+    ) -> io::Result<ConflictAction> {
+        let dirs = from_meta.is_dir() && to_meta.is_dir();
+        let files = from_meta.is_file() && to_meta.is_file();
+
+        let action = match self {
+            ConflictPolicy::Error => ConflictAction::Error,
+            ConflictPolicy::RenameWithSuffix => ConflictAction::Rename,
+            ConflictPolicy::Custom(callback) => callback(from, to),
+            _ if dirs => ConflictAction::Merge,
+            ConflictPolicy::Skip => ConflictAction::Skip,
+            ConflictPolicy::Overwrite => ConflictAction::Overwrite,
+            ConflictPolicy::MergeDirectories => ConflictAction::Error,
+            ConflictPolicy::OverwriteIfNewer if files => {
+                if from_meta.modified()? > to_meta.modified()? {
+                    ConflictAction::Overwrite
+                } else {
+                    ConflictAction::Skip
+                }
+            }
+            ConflictPolicy::OverwriteIfDifferent if files => {
+                if is_different(from, from_meta, to, to_meta)? {
+                    ConflictAction::Overwrite
+                } else {
+                    ConflictAction::Skip
+                }
+            }
+            // An entry of another type is always different.
+            ConflictPolicy::OverwriteIfNewer | ConflictPolicy::OverwriteIfDifferent => {
+                ConflictAction::Overwrite
+            }
+        };
+
+        Ok(action)
+    }
+}
+
+impl fmt::Debug for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictPolicy::Overwrite => write!(f, "Overwrite"),
+            ConflictPolicy::Skip => write!(f, "Skip"),
+            ConflictPolicy::Error => write!(f, "Error"),
+            ConflictPolicy::OverwriteIfNewer => write!(f, "OverwriteIfNewer"),
+            ConflictPolicy::OverwriteIfDifferent => write!(f, "OverwriteIfDifferent"),
+            ConflictPolicy::RenameWithSuffix => write!(f, "RenameWithSuffix"),
+            ConflictPolicy::MergeDirectories => write!(f, "MergeDirectories"),
+            ConflictPolicy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Compares two files by size, then by content.
+pub(crate) fn is_different(
+    from: &Path,
+    from_meta: &Metadata,
+    to: &Path,
+    to_meta: &Metadata,
+) -> io::Result<bool> {
+    if from_meta.len() != to_meta.len() {
+        return Ok(true);
+    }
+
+    let mut from = BufReader::new(File::open(from)?);
+    let mut to = BufReader::new(File::open(to)?);
+    let mut from_buf = [0; 8192];
+    let mut to_buf = [0; 8192];
+
+    loop {
+        let read = from.read(&mut from_buf)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        to.read_exact(&mut to_buf[..read])?;
+        if from_buf[..read] != to_buf[..read] {
+            return Ok(true);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Copying of file content. On Linux a file is cloned as a reflink or copied
+//! in the kernel with `copy_file_range` if possible, everywhere else and as
+//! the last resort it's read and written through a buffer.
+use crate::{
+    error::{Result, ResultExt},
+    progress::Monitor,
+    utils, walk,
+};
+use std::{
+    fs::{remove_file, rename, symlink_metadata, File, Metadata, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+/// Whether files are cloned as copy-on-write reflinks, like the `--reflink`
+/// option of GNU `cp`. A reflink shares the blocks of the source until one
+/// of the files is changed, so it's created instantly and takes no space.
+/// Reflinks are supported on Linux by Btrfs, XFS and a few other
+/// filesystems.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Reflink {
+    /// Clone the file if the filesystem supports it, copy it otherwise.
+    #[default]
+    Auto,
+    /// Clone the file or fail with the error of the filesystem.
+    Always,
+    /// Always copy the content.
+    Never,
+}
+
+/// How the content of a file was copied, see
+/// [`Event::FileCopied`](crate::Event::FileCopied).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyMethod {
+    /// The file was cloned as a reflink.
+    Reflink,
+    /// The content was copied in the kernel with `copy_file_range`.
+    CopyFileRange,
+    /// The content was read and written through a buffer.
+    Buffered,
+}
+
+/// Size of the chunks reported by [`Event::BytesCopied`](crate::Event::BytesCopied),
+/// the cancel token is checked between them.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Copies the content and permissions of the `from` file, trying a reflink,
+/// `copy_file_range` and a buffered copy in this order.
+///
+/// The content is written to a temporary file next to `to`, which is renamed
+/// over `to` when it's complete. An existing `to` is left untouched if the
+/// copy fails or is cancelled, only the temporary file is deleted. Unlike
+/// [`std::fs::copy`], an existing `to` is replaced rather than written to: a
+/// symlink isn't followed, hard links of `to` keep the old content and the
+/// owner and extended attributes of `to` are lost. Copying
+/// a file onto itself or one of its hard links fails with
+/// [`Error::DestinationExists`](crate::Error::DestinationExists).
+pub(crate) fn copy(
+    op: &'static str,
+    from: &Path,
+    from_meta: &Metadata,
+    to: &Path,
+    reflink: Reflink,
+    monitor: &Monitor,
+) -> Result<CopyMethod> {
+    let reader = File::open(from).between(op, from, to)?;
+    if let Ok(to_meta) = symlink_metadata(to) {
+        let from_meta = reader.metadata().between(op, from, to)?;
+        if walk::same_file(&from_meta, &to_meta) {
+            err!(DestinationExists, op, from, to)
+        }
+    }
+    let (temp, writer) = utils::create_temp(to, |temp| {
+        OpenOptions::new().write(true).create_new(true).open(temp)
+    })
+    .between(op, from, to)?;
+    let mut copy = Copy {
+        op,
+        from,
+        to,
+        reader,
+        writer,
+        monitor,
+    };
+
+    let res = copy.content(reflink, from_meta.len()).and_then(|method| {
+        copy.writer
+            .set_permissions(from_meta.permissions())
+            .between(op, from, to)
+            .map(|()| method)
+    });
+    drop(copy);
+
+    let res = res.and_then(|method| rename(&temp, to).between(op, from, to).map(|()| method));
+    if res.is_err() {
+        remove_file(&temp).ok();
+    }
+    res
+}
+
+/// A file being copied.
+struct Copy<'a> {
+    op: &'static str,
+    from: &'a Path,
+    to: &'a Path,
+    reader: File,
+    writer: File,
+    monitor: &'a Monitor<'a>,
+}
+
+impl Copy<'_> {
+    fn content(&mut self, reflink: Reflink, len: u64) -> Result<CopyMethod> {
+        self.monitor.check_cancel(self.op, self.from)?;
+
+        if reflink != Reflink::Never {
+            match sys::clone(&self.reader, &self.writer) {
+                Ok(()) => {
+                    if len > 0 {
+                        self.monitor.bytes_copied(len);
+                    }
+                    return Ok(CopyMethod::Reflink);
+                }
+                Err(e) if reflink == Reflink::Always => {
+                    return Err(e).between(self.op, self.from, self.to)
+                }
+                Err(_) => {}
+            }
+        }
+
+        if self.copy_range()? {
+            return Ok(CopyMethod::CopyFileRange);
+        }
+        self.buffered()?;
+        Ok(CopyMethod::Buffered)
+    }
+
+    /// Copies the content in chunks with `copy_file_range`. Returns `false`
+    /// if the files don't support it, which is known before anything is
+    /// copied.
+    fn copy_range(&mut self) -> Result<bool> {
+        let mut copied = false;
+        loop {
+            self.monitor.check_cancel(self.op, self.from)?;
+
+            match sys::copy_range(&self.reader, &self.writer, CHUNK_SIZE) {
+                // Some special files report no content, they are read
+                // instead.
+                Ok(0) => return Ok(copied),
+                Ok(written) => {
+                    copied = true;
+                    self.monitor.bytes_copied(written as u64);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if !copied && sys::is_unsupported(&e) => return Ok(false),
+                Err(e) => return Err(e).between(self.op, self.from, self.to),
+            }
+        }
+    }
+
+    fn buffered(&mut self) -> Result<()> {
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            self.monitor.check_cancel(self.op, self.from)?;
+
+            let read = match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).between(self.op, self.from, self.to),
+            };
+            self.writer
+                .write_all(&buf[..read])
+                .between(self.op, self.from, self.to)?;
+            self.monitor.bytes_copied(read as u64);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{fs::File, io, os::unix::io::AsRawFd, ptr};
+
+    pub fn clone(reader: &File, writer: &File) -> io::Result<()> {
+        // SAFETY: both descriptors are open for the lifetime of the files.
+        let res = unsafe { libc::ioctl(writer.as_raw_fd(), libc::FICLONE, reader.as_raw_fd()) };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn copy_range(reader: &File, writer: &File, len: usize) -> io::Result<usize> {
+        // SAFETY: both descriptors are open for the lifetime of the files,
+        // null offsets make the call use and advance the file positions.
+        let res = unsafe {
+            libc::copy_file_range(
+                reader.as_raw_fd(),
+                ptr::null_mut(),
+                writer.as_raw_fd(),
+                ptr::null_mut(),
+                len,
+                0,
+            )
+        };
+
+        if res >= 0 {
+            Ok(res as usize)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Returns `true` if the error means that the kernel or the filesystems
+    /// can't copy these files, so they have to be read instead.
+    pub fn is_unsupported(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(
+                libc::ENOSYS
+                    | libc::EXDEV
+                    | libc::EINVAL
+                    | libc::EOPNOTSUPP
+                    | libc::EPERM
+                    | libc::EBADF
+            )
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::{fs::File, io};
+
+    pub fn clone(_: &File, _: &File) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reflinks are not supported on this platform",
+        ))
+    }
+
+    pub fn copy_range(_: &File, _: &File, _: usize) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn is_unsupported(_: &io::Error) -> bool {
+        true
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{batch::BatchReport, cancel::Summary, report::MoveReport};
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// A specialized `Result` type for `rfm` operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Describes where an error happened: the name of the operation (`"cp"`,
+/// `"rm"`, ...), the source path and the destination path (if the operation
+/// has one), and the underlying I/O error, if any.
+///
+/// Errors of [`mv_with`](crate::mv_with) also carry a [`MoveReport`] of
+/// what was done before the failure, cancelled operations carry
+/// a [`Summary`] of what was completed, and [`Error::Batch`] carries
+/// a [`BatchReport`] with the error of every failed path.
+#[derive(Debug)]
+pub struct Context {
+    op: &'static str,
+    from: Option<PathBuf>,
+    to: Option<PathBuf>,
+    source: Option<io::Error>,
+    report: Option<Box<MoveReport>>,
+    summary: Option<Summary>,
+    batch: Option<Box<BatchReport>>,
+}
+
+impl Context {
+    pub(crate) fn new(op: &'static str, from: Option<&Path>, to: Option<&Path>) -> Context {
+        Context {
+            op,
+            from: from.map(Path::to_path_buf),
+            to: to.map(Path::to_path_buf),
+            source: None,
+            report: None,
+            summary: None,
+            batch: None,
+        }
+    }
+
+    pub(crate) fn set_summary(&mut self, summary: Summary) {
+        self.summary = Some(summary);
+    }
+
+    pub(crate) fn set_batch(&mut self, report: BatchReport) {
+        self.batch = Some(Box::new(report));
+    }
+
+    /// Name of the operation that failed.
+    pub fn op(&self) -> &'static str {
+        self.op
+    }
+
+    /// The path that was being processed when the error happened.
+    pub fn from_path(&self) -> Option<&Path> {
+        self.from.as_deref()
+    }
+
+    /// The destination path, for operations that have one.
+    pub fn to_path(&self) -> Option<&Path> {
+        self.to.as_deref()
+    }
+
+    /// What [`mv_with`](crate::mv_with) has done before the error happened.
+    pub fn report(&self) -> Option<&MoveReport> {
+        self.report.as_deref()
+    }
+
+    /// What a cancelled operation has completed.
+    pub fn summary(&self) -> Option<Summary> {
+        self.summary
+    }
+
+    /// What happened to every path of a batch with failed paths.
+    pub fn batch(&self) -> Option<&BatchReport> {
+        self.batch.as_deref()
+    }
+}
+
+/// The error type for `rfm` operations.
+///
+/// Every variant carries a [`Context`] with the operation name and the paths
+/// involved, so a failure deep inside a tree points to the exact entry that
+/// broke. `Error` converts to and from [`std::io::Error`], so it can be used
+/// with the `?` operator in functions returning `std::io::Result`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A list of paths passed to the operation is empty.
+    EmptyInput(Context),
+    /// More than one path was passed to an operation that takes one, e.g.
+    /// [`cp_with`](crate::cp_with) with
+    /// [`CopyOptions::no_target_directory`](crate::CopyOptions::no_target_directory).
+    TooManyPaths(Context),
+    /// The file or directory does not exist.
+    NotFound(Context),
+    /// A directory was expected, but the path points to something else.
+    NotADirectory(Context),
+    /// The destination path already exists.
+    DestinationExists(Context),
+    /// The current process does not have the permission to access the path.
+    PermissionDenied(Context),
+    /// The operation can't be performed across filesystems.
+    CrossDevice(Context),
+    /// A symlink was found, and the [`SymlinkPolicy`](crate::SymlinkPolicy)
+    /// doesn't allow it.
+    Symlink(Context),
+    /// Following symlinks leads to a directory that is already being walked.
+    SymlinkLoop(Context),
+    /// The path is refused by the [`Guard`](crate::Guard) of the operation.
+    Protected(Context),
+    /// The operation was stopped with a [`CancelToken`](crate::CancelToken).
+    /// The context has a [`Summary`] of what was completed.
+    Cancelled(Context),
+    /// Some of the paths passed to an operation in
+    /// [`ErrorMode::Continue`](crate::ErrorMode::Continue) failed. The
+    /// context has a [`BatchReport`] with their errors.
+    Batch(Context),
+    /// Any other I/O error.
+    Io(Context),
+}
+
+impl Error {
+    /// Builds an error from an I/O error, choosing the variant by its kind.
+    pub(crate) fn from_io(
+        op: &'static str,
+        err: io::Error,
+        from: Option<&Path>,
+        to: Option<&Path>,
+    ) -> Error {
+        let mut ctx = Context::new(op, from, to);
+        let constructor = match err.kind() {
+            io::ErrorKind::NotFound => Error::NotFound,
+            io::ErrorKind::NotADirectory => Error::NotADirectory,
+            io::ErrorKind::AlreadyExists => Error::DestinationExists,
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+            io::ErrorKind::CrossesDevices => Error::CrossDevice,
+            _ => Error::Io,
+        };
+        ctx.source = Some(err);
+        constructor(ctx)
+    }
+
+    /// Returns the context of the error.
+    pub fn context(&self) -> &Context {
+        match self {
+            Error::EmptyInput(ctx)
+            | Error::TooManyPaths(ctx)
+            | Error::NotFound(ctx)
+            | Error::NotADirectory(ctx)
+            | Error::DestinationExists(ctx)
+            | Error::PermissionDenied(ctx)
+            | Error::CrossDevice(ctx)
+            | Error::Symlink(ctx)
+            | Error::SymlinkLoop(ctx)
+            | Error::Protected(ctx)
+            | Error::Cancelled(ctx)
+            | Error::Batch(ctx)
+            | Error::Io(ctx) => ctx,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        match self {
+            Error::EmptyInput(ctx)
+            | Error::TooManyPaths(ctx)
+            | Error::NotFound(ctx)
+            | Error::NotADirectory(ctx)
+            | Error::DestinationExists(ctx)
+            | Error::PermissionDenied(ctx)
+            | Error::CrossDevice(ctx)
+            | Error::Symlink(ctx)
+            | Error::SymlinkLoop(ctx)
+            | Error::Protected(ctx)
+            | Error::Cancelled(ctx)
+            | Error::Batch(ctx)
+            | Error::Io(ctx) => ctx,
+        }
+    }
+
+    /// Attaches the report of the failed move.
+    pub(crate) fn with_report(mut self, report: MoveReport) -> Error {
+        self.context_mut().report = Some(Box::new(report));
+        self
+    }
+
+    /// Name of the operation that failed.
+    pub fn op(&self) -> &'static str {
+        self.context().op()
+    }
+
+    /// The path that was being processed when the error happened.
+    pub fn from_path(&self) -> Option<&Path> {
+        self.context().from_path()
+    }
+
+    /// The destination path, for operations that have one.
+    pub fn to_path(&self) -> Option<&Path> {
+        self.context().to_path()
+    }
+
+    /// What [`mv_with`](crate::mv_with) has done before the error happened:
+    /// which sources were moved, partially moved or left untouched.
+    pub fn report(&self) -> Option<&MoveReport> {
+        self.context().report()
+    }
+
+    /// What the operation has completed before it was cancelled, see
+    /// [`Error::Cancelled`].
+    pub fn summary(&self) -> Option<Summary> {
+        self.context().summary()
+    }
+
+    /// What happened to every path passed to the operation, see
+    /// [`Error::Batch`].
+    pub fn batch(&self) -> Option<&BatchReport> {
+        self.context().batch()
+    }
+
+    /// Returns the corresponding [`std::io::ErrorKind`] for this error.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::EmptyInput(_) => io::ErrorKind::InvalidInput,
+            Error::TooManyPaths(_) => io::ErrorKind::InvalidInput,
+            Error::NotFound(_) => io::ErrorKind::NotFound,
+            Error::NotADirectory(_) => io::ErrorKind::NotADirectory,
+            Error::DestinationExists(_) => io::ErrorKind::AlreadyExists,
+            Error::PermissionDenied(_) => io::ErrorKind::PermissionDenied,
+            Error::CrossDevice(_) => io::ErrorKind::CrossesDevices,
+            Error::Symlink(_) => io::ErrorKind::InvalidInput,
+            Error::SymlinkLoop(_) => io::ErrorKind::Other,
+            Error::Protected(_) => io::ErrorKind::PermissionDenied,
+            Error::Cancelled(_) => io::ErrorKind::Other,
+            Error::Batch(_) => io::ErrorKind::Other,
+            Error::Io(ctx) => ctx
+                .source
+                .as_ref()
+                .map_or(io::ErrorKind::Other, io::Error::kind),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Error::EmptyInput(_) => "no paths were passed",
+            Error::TooManyPaths(_) => "only one path can be passed",
+            Error::NotFound(_) => "file or directory does not exist",
+            Error::NotADirectory(_) => "isn't a directory",
+            Error::DestinationExists(_) => "destination already exists",
+            Error::PermissionDenied(_) => "permission denied",
+            Error::CrossDevice(_) => "paths are on different filesystems",
+            Error::Symlink(_) => "symlinks are not allowed",
+            Error::SymlinkLoop(_) => "symlink loop detected",
+            Error::Protected(_) => "path is protected",
+            Error::Cancelled(_) => "cancelled",
+            Error::Batch(_) => "some of the paths failed",
+            Error::Io(_) => "I/O error",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ctx = self.context();
+
+        write!(f, "{}", ctx.op)?;
+        if let Some(from) = &ctx.from {
+            write!(f, " {:?}", from)?;
+        }
+        if let Some(to) = &ctx.to {
+            write!(f, " -> {:?}", to)?;
+        }
+        match &ctx.source {
+            Some(source) => write!(f, ": {}", source),
+            None => write!(f, ": {}", self.description()),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.context()
+            .source
+            .as_ref()
+            .map(|e| e as &(dyn error::Error + 'static))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        // Unwrap errors that were converted to `io::Error` earlier, so the
+        // context isn't lost on a round trip.
+        match err.downcast::<Error>() {
+            Ok(inner) => inner,
+            Err(err) => Error::from_io("io", err, None, None),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+/// Attaches the operation name and paths to an I/O result.
+pub(crate) trait ResultExt<T> {
+    fn at(self, op: &'static str, path: &Path) -> Result<T>;
+    fn between(self, op: &'static str, from: &Path, to: &Path) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for io::Result<T> {
+    fn at(self, op: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|e| Error::from_io(op, e, Some(path), None))
+    }
+
+    fn between(self, op: &'static str, from: &Path, to: &Path) -> Result<T> {
+        self.map_err(|e| Error::from_io(op, e, Some(from), Some(to)))
+    }
+}
@@ -0,0 +1,212 @@
+//! The changes operations make to the filesystem. An operation decides what
+//! to do in one place and makes every change through an [`Exec`], which
+//! performs it on [`Disk`] or records it in a [`Plan`](crate::Plan), so dry
+//! runs and real runs share the same decisions.
+use crate::{
+    conflict::ConflictAction,
+    engine::{self, Reflink},
+    error::{Result, ResultExt},
+    parallel,
+    preserve::Preserve,
+    progress::Monitor,
+    symlink::copy_link,
+    utils, Entry,
+};
+use std::{
+    fs::{self, remove_dir_all, symlink_metadata, DirBuilder, File, Metadata, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub(crate) trait Exec: Sync {
+    /// Returns the metadata of `path` without following symlinks, with the
+    /// path holding its content. For a planned copy it's the source.
+    fn lookup(&self, path: &Path) -> Option<(PathBuf, Metadata)>;
+
+    /// Returns `true` if something exists at `path`.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Returns `true` if `path` is a directory, following symlinks.
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Returns the number of threads to use for the `threads` option.
+    fn threads(&self, threads: Option<usize>) -> usize;
+
+    /// Called with the decision of the conflict policy for an existing
+    /// destination.
+    fn resolved(&self, _from: &Path, _to: &Path, _action: ConflictAction) {}
+
+    fn create_dir(&self, path: &Path, mode: Option<u32>) -> io::Result<()>;
+
+    /// Creates an empty file, or truncates or only touches an existing one.
+    fn create_file(&self, path: &Path, truncate: bool) -> io::Result<()>;
+
+    /// Creates the directory `to` for the content of the `from` directory.
+    fn copy_dir(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies a file or recreates a symlink, replacing an existing file or
+    /// symlink at `to` once the copy is complete.
+    fn copy_file(
+        &self,
+        op: &'static str,
+        entry: &Entry,
+        to: &Path,
+        reflink: Reflink,
+        monitor: &Monitor,
+    ) -> Result<()>;
+
+    /// Applies the `preserve` metadata of `from` to its copy `to`.
+    fn preserve(
+        &self,
+        op: &'static str,
+        preserve: Preserve,
+        from: &Path,
+        from_meta: &Metadata,
+        to: &Path,
+    ) -> Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes an empty directory.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes a file, a symlink or a directory with its content.
+    fn remove_tree(&self, op: &'static str, path: &Path) -> Result<()>;
+
+    /// Moves the entry to the trash.
+    #[cfg(target_os = "linux")]
+    fn trash(&self, path: &Path) -> Result<()>;
+}
+
+/// Performs the changes right away.
+pub(crate) struct Disk;
+
+impl Exec for Disk {
+    fn lookup(&self, path: &Path) -> Option<(PathBuf, Metadata)> {
+        symlink_metadata(path)
+            .ok()
+            .map(|meta| (path.to_path_buf(), meta))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        symlink_metadata(path).is_ok()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn threads(&self, threads: Option<usize>) -> usize {
+        parallel::threads(threads)
+    }
+
+    fn create_dir(&self, path: &Path, mode: Option<u32>) -> io::Result<()> {
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(mode);
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        builder.create(path)
+    }
+
+    fn create_file(&self, path: &Path, truncate: bool) -> io::Result<()> {
+        if truncate {
+            File::create(path).map(drop)
+        } else {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+        }
+    }
+
+    fn copy_dir(&self, _from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir(to)
+    }
+
+    fn copy_file(
+        &self,
+        op: &'static str,
+        entry: &Entry,
+        to: &Path,
+        reflink: Reflink,
+        monitor: &Monitor,
+    ) -> Result<()> {
+        if entry.is_symlink() {
+            // Like a file, the symlink replaces an existing one only once
+            // it's created.
+            let (temp, ()) = utils::create_temp(to, |temp| copy_link(entry.path(), temp)).between(
+                op,
+                entry.path(),
+                to,
+            )?;
+            if let Err(e) = fs::rename(&temp, to) {
+                fs::remove_file(&temp).ok();
+                return Err(e).between(op, entry.path(), to);
+            }
+            Ok(())
+        } else {
+            let method = engine::copy(op, entry.path(), entry.metadata(), to, reflink, monitor)?;
+            monitor.file_copied(entry.path(), method);
+            Ok(())
+        }
+    }
+
+    fn preserve(
+        &self,
+        op: &'static str,
+        preserve: Preserve,
+        from: &Path,
+        from_meta: &Metadata,
+        to: &Path,
+    ) -> Result<()> {
+        preserve.apply(op, from, from_meta, to)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn remove_tree(&self, op: &'static str, path: &Path) -> Result<()> {
+        if symlink_metadata(path).at(op, path)?.is_dir() {
+            remove_dir_all(path).at(op, path)
+        } else {
+            fs::remove_file(path).at(op, path)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn trash(&self, path: &Path) -> Result<()> {
+        crate::trash::trash_one(path)
+    }
+}
+
+/// Creates the directory with its missing parents.
+pub(crate) fn create_dir_all(exec: &dyn Exec, op: &'static str, dir: &Path) -> Result<()> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .take_while(|p| !exec.exists(p))
+        .collect();
+
+    for dir in missing.into_iter().rev() {
+        exec.create_dir(dir, None).at(op, dir)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use crate::walk::Entry;
+use std::{fmt, path::Path, sync::Arc};
+
+type Predicate = Arc<dyn Fn(&Entry) -> bool + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    // `?`
+    AnyChar,
+    // `*`
+    Star,
+    // `**` not followed by `/`
+    AnyPath,
+    // `**/`
+    AnyDirs,
+    // `[...]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A shell-style glob pattern.
+#[derive(Debug, Clone)]
+struct Glob {
+    tokens: Vec<Token>,
+    // Pattern without `/` is matched against the file name only.
+    name_only: bool,
+    // Pattern with a trailing `/` matches directories only.
+    dir_only: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Glob {
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let name_only = !pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = vec![];
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::AnyDirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                }
+                '*' => {
+                    tokens.push(Token::Star);
+                    i += 1;
+                }
+                '?' => {
+                    tokens.push(Token::AnyChar);
+                    i += 1;
+                }
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        tokens.push(token);
+                        i += len + 1;
+                    }
+                    None => {
+                        tokens.push(Token::Char('['));
+                        i += 1;
+                    }
+                },
+                c => {
+                    tokens.push(Token::Char(c));
+                    i += 1;
+                }
+            }
+        }
+
+        Glob {
+            tokens,
+            name_only,
+            dir_only,
+        }
+    }
+
+    fn is_match(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let text = if self.name_only {
+            rel.rsplit('/').next().unwrap_or(rel)
+        } else {
+            rel
+        };
+        let text: Vec<char> = text.chars().collect();
+
+        matches(&self.tokens, &text)
+    }
+}
+
+/// Parses a character class after the opening `[`, returns the token and the
+/// number of consumed chars (including the closing `]`).
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = vec![];
+    let start = i;
+
+    while i < chars.len() {
+        let c = chars[i];
+        // `]` right after the opening bracket is a literal.
+        if c == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+
+    None
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+
+    match token {
+        Token::Char(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+        Token::AnyChar => text.first().is_some_and(|c| *c != '/') && matches(rest, &text[1..]),
+        Token::Class { negated, ranges } => match text.first() {
+            Some(c) if *c != '/' => {
+                let in_class = ranges.iter().any(|(from, to)| from <= c && c <= to);
+                in_class != *negated && matches(rest, &text[1..])
+            }
+            _ => false,
+        },
+        Token::Star => {
+            for i in 0..=text.len() {
+                if matches(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Token::AnyPath => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        Token::AnyDirs => {
+            matches(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && matches(rest, &text[i + 1..]))
+        }
+    }
+}
+
+/// Include/exclude rules applied to the entries of a tree.
+///
+/// Patterns are shell-style globs (`*`, `?`, `[a-z]` and `**` for any
+/// number of directories), matched against the path relative to the root
+/// of the operation, with `/` as the separator. A pattern without `/` is
+/// matched against the file name at any depth, a pattern with a trailing
+/// `/` matches only directories.
+///
+/// - An entry matching an exclude pattern is skipped. If it's a directory,
+///   its whole subtree is skipped.
+/// - If there are include patterns, files and symlinks not matching any of
+///   them are skipped. Directories are still descended into.
+/// - A directory for which the predicate returns `false` is skipped with its
+///   whole subtree.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::Filter;
+///
+///  let filter = Filter::new()
+///      .exclude("target/")
+///      .exclude("*.tmp")
+///      .predicate(|entry| entry.metadata().len() < 1024 * 1024);
+/// ```
+#[derive(Clone, Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    predicate: Option<Predicate>,
+}
+
+impl Filter {
+    /// Creates a filter that accepts every entry.
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    /// Adds a pattern for entries to keep.
+    pub fn include(mut self, pattern: &str) -> Filter {
+        self.include.push(Glob::new(pattern));
+        self
+    }
+
+    /// Adds a pattern for entries to skip.
+    pub fn exclude(mut self, pattern: &str) -> Filter {
+        self.exclude.push(Glob::new(pattern));
+        self
+    }
+
+    /// Sets a function that decides whether an entry is kept.
+    pub fn predicate<F>(mut self, predicate: F) -> Filter
+    where
+        F: Fn(&Entry) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Returns `true` if the filter accepts every entry.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.predicate.is_none()
+    }
+
+    /// Returns `true` if the directory is selected by the filter itself,
+    /// not only descended into: there are no include patterns or one of
+    /// them matches it.
+    pub(crate) fn includes_dir(&self, root: &Path, entry: &Entry) -> bool {
+        let rel = relative(root, entry.path());
+        self.include.is_empty() || self.include.iter().any(|g| g.is_match(&rel, true))
+    }
+
+    /// Returns `true` if the entry has to be skipped. `root` is the path the
+    /// relative path of the entry is computed from.
+    pub(crate) fn skips(&self, root: &Path, entry: &Entry) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let rel = relative(root, entry.path());
+        let is_dir = entry.is_dir();
+
+        if self.exclude.iter().any(|g| g.is_match(&rel, is_dir)) {
+            return true;
+        }
+
+        if !is_dir
+            && !self.include.is_empty()
+            && !self.include.iter().any(|g| g.is_match(&rel, is_dir))
+        {
+            return true;
+        }
+
+        match &self.predicate {
+            Some(predicate) => !predicate(entry),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("include", &self.include)
+            .field("exclude", &self.exclude)
+            .field(
+                "predicate",
+                &self.predicate.as_ref().map(|_| "Fn(&Entry) -> bool"),
+            )
+            .finish()
+    }
+}
+
+/// Returns the path of `entry` relative to `root` with `/` as the separator.
+/// If `entry` is `root`, returns its file name.
+fn relative(root: &Path, entry: &Path) -> String {
+    match entry.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        _ => entry
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::Result;
+#[cfg(target_os = "linux")]
+use crate::utils;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Protects paths that must never be deleted or moved away by
+/// [`rm_with`](crate::rm_with), [`clean_with`](crate::clean_with),
+/// [`mv_with`](crate::mv_with) and `trash_with`.
+///
+/// By default the guard refuses filesystem roots, the home directory of the
+/// user and its ancestors, and mount points. More paths are protected with
+/// [`protect`](Guard::protect): a protected path can't be deleted, and
+/// neither can any of its ancestors. Paths are resolved before the checks,
+/// so `./../..` or a symlinked parent don't get around the guard. A symlink
+/// itself is not resolved, because the operations delete the link and not
+/// its target.
+///
+/// A refused path fails the operation with
+/// [`Error::Protected`](crate::Error::Protected) before anything is touched.
+/// [`Guard::disabled`] is the explicit override.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{rm_with, Guard, RemoveOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let options = RemoveOptions::new().guard(Guard::new().protect("./dir/keep"));
+///
+///  rm_with(&vec![&dir], &options)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Guard {
+    roots: bool,
+    home: bool,
+    mount_points: bool,
+    protected: Vec<PathBuf>,
+}
+
+impl Guard {
+    /// Creates a guard with the default protections.
+    pub fn new() -> Guard {
+        Guard::default()
+    }
+
+    /// Creates a guard that allows everything.
+    pub fn disabled() -> Guard {
+        Guard {
+            roots: false,
+            home: false,
+            mount_points: false,
+            protected: Vec::new(),
+        }
+    }
+
+    /// Refuses filesystem roots. Enabled by default.
+    pub fn roots(mut self, yes: bool) -> Guard {
+        self.roots = yes;
+        self
+    }
+
+    /// Refuses the home directory and its ancestors. Enabled by default.
+    pub fn home(mut self, yes: bool) -> Guard {
+        self.home = yes;
+        self
+    }
+
+    /// Refuses mount points. Enabled by default.
+    pub fn mount_points(mut self, yes: bool) -> Guard {
+        self.mount_points = yes;
+        self
+    }
+
+    /// Adds a path to protect together with its ancestors. A path that
+    /// doesn't exist is compared as it is.
+    pub fn protect<P: AsRef<Path>>(mut self, path: P) -> Guard {
+        self.protected.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Returns [`Error::Protected`](crate::Error::Protected) if `path` must
+    /// not be touched by `op`.
+    pub(crate) fn check(&self, op: &'static str, path: &Path) -> Result<()> {
+        if !self.roots && !self.home && !self.mount_points && self.protected.is_empty() {
+            return Ok(());
+        }
+
+        // A path that can't be resolved is left for the operation to report,
+        // there's nothing to protect yet.
+        let resolved = match resolve(path) {
+            Ok(resolved) => resolved,
+            Err(_) => return Ok(()),
+        };
+        let contains = |p: &Path| {
+            let p = p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+            p.starts_with(&resolved)
+        };
+
+        if (self.roots && resolved.parent().is_none())
+            || (self.home && home().is_some_and(|home| contains(&home)))
+            || (self.mount_points && is_mount_point(&resolved))
+            || self.protected.iter().any(|p| contains(p))
+        {
+            err!(Protected, op, path)
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Guard {
+        Guard {
+            roots: true,
+            home: true,
+            mount_points: true,
+            protected: Vec::new(),
+        }
+    }
+}
+
+/// Returns the absolute path without `.`, `..` and symlinks, except for the
+/// last component if it is a symlink.
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    if !path.symlink_metadata()?.file_type().is_symlink() {
+        return path.canonicalize();
+    }
+
+    // A symlink always has a name, `..` and `.` are never symlinks.
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    Ok(parent.join(path.file_name().unwrap()))
+}
+
+fn home() -> Option<PathBuf> {
+    let home = if cfg!(windows) {
+        env::var_os("USERPROFILE")
+    } else {
+        env::var_os("HOME")
+    };
+    home.filter(|home| !home.is_empty()).map(PathBuf::from)
+}
+
+/// Returns `true` if a filesystem is mounted at the resolved `path`.
+fn is_mount_point(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = |p: &Path| p.symlink_metadata().map(|meta| meta.dev()).ok();
+        if let Some(parent) = path.parent() {
+            if dev(path).is_some() && dev(path) != dev(parent) {
+                return true;
+            }
+        }
+    }
+
+    // Bind mounts stay on the device of their parent.
+    #[cfg(target_os = "linux")]
+    {
+        if utils::mount_points().iter().any(|point| point == path) {
+            return true;
+        }
+    }
+
+    false
+}
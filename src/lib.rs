@@ -4,29 +4,106 @@
 //! Function naming is similar to Unix commands
 //! _(`ls`, `mkdir`, `mv`, `rm`, `cp`, `touch` etc.)_,
 //! so you can easily figure out how to use it.
-mod utils;
-use std::{
-    fs::{copy, create_dir, read_dir, remove_dir_all, remove_file},
-    io::{Error, ErrorKind, Result},
-    path::PathBuf,
-};
-
+//!
+//! Paths are taken as anything implementing `AsRef<Path>` (`&str`, `&Path`,
+//! `PathBuf` etc.), and lists of paths as arrays, vectors or iterators of
+//! them, so `rfm::rm(["./dir", "./file.txt"])` works as it is.
 macro_rules! err {
-    ($text:expr, $kind:expr) => {
-        return Err(Error::new($kind, $text))
+    ($kind:ident, $op:expr) => {
+        return Err($crate::Error::$kind($crate::Context::new($op, None, None)))
+    };
+
+    ($kind:ident, $op:expr, $from:expr) => {
+        return Err($crate::Error::$kind($crate::Context::new(
+            $op,
+            Some($from),
+            None,
+        )))
     };
 
-    ($text:expr) => {
-        err!($text, ErrorKind::Other)
+    ($kind:ident, $op:expr, $from:expr, $to:expr) => {
+        return Err($crate::Error::$kind($crate::Context::new(
+            $op,
+            Some($from),
+            Some($to),
+        )))
     };
 }
 
+mod batch;
+mod cancel;
+mod conflict;
+mod engine;
+mod error;
+mod exec;
+mod filter;
+mod guard;
+mod naming;
+mod options;
+mod parallel;
+mod plan;
+mod preserve;
+mod progress;
+mod prune;
+mod report;
+mod symlink;
+mod transfer;
+#[cfg(target_os = "linux")]
+mod trash;
+mod usage;
+mod utils;
+mod walk;
+use std::{
+    collections::HashSet,
+    fs::metadata,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub use batch::{BatchReport, ErrorMode};
+pub use cancel::{CancelToken, Summary};
+pub use conflict::{ConflictAction, ConflictPolicy};
+pub use engine::{CopyMethod, Reflink};
+use error::ResultExt;
+pub use error::{Context, Error, Result};
+use exec::{Disk, Exec};
+pub use filter::Filter;
+pub use guard::Guard;
+use naming::Namer;
+pub use naming::Naming;
+#[cfg(target_os = "linux")]
+pub use options::TrashOptions;
+pub use options::{
+    CleanOptions, CopyOptions, ExtractOptions, LsOptions, MkdirOptions, PruneOptions,
+    RemoveOptions, SizeOptions, TouchOptions,
+};
+use parallel::{Claims, Subtree};
+pub use plan::{Action, Conflict, Plan};
+pub use preserve::Preserve;
+use progress::Monitor;
+pub use progress::{Event, Progress};
+pub use prune::{prune, SortBy};
+pub use report::{MoveReport, MoveStatus, MoveStrategy, Moved};
+pub use symlink::SymlinkPolicy;
+#[cfg(target_os = "linux")]
+pub use trash::{
+    empty_trash, empty_trash_with, list_trash, list_trash_with, restore, trash, trash_with,
+    TrashItem,
+};
+use usage::{Counter, DuTree};
+pub use usage::{DuNode, SizeMode, Usage};
+pub use walk::{Entry, Walk};
+
 // ------------------------------------------------------------------------ //
 
 /// Read the directory/directories and return the content.
 /// `dir` - takes the path to the directory whose contents you want to
 /// retrieve.
 ///
+/// A symlink to a directory is listed like the directory, the returned paths
+/// start with the symlink.
+///
 /// # Errors
 ///
 /// This function will return an error in the following situations, but is not
@@ -34,8 +111,9 @@ macro_rules! err {
 ///
 /// - Param `dir` contains file or directory does not exist.
 /// - Param `dir` contains file or directory with invalid name.
+/// - Param `dir` isn't a directory.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
 ///
 /// # Example
 ///
@@ -47,22 +125,95 @@ macro_rules! err {
 ///
 ///  let directory_contents = ls(&dir)?;
 /// ```
-pub fn ls(dir: &PathBuf) -> Result<Vec<PathBuf>> {
-    if dir.is_file() {
-        err!("{} - isn't a directory", ErrorKind::InvalidInput)
+pub fn ls(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    ls_with(dir, &LsOptions::default())
+}
+
+/// Same as [`ls`], but returns only the content accepted by the filter from
+/// `options`.
+///
+/// # Errors
+///
+/// See [`ls`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{ls_with, Filter, LsOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let options = LsOptions::new().filter(Filter::new().include("*.txt"));
+///
+///  let text_files = ls_with(&dir, &options)?;
+/// ```
+pub fn ls_with(dir: impl AsRef<Path>, options: &LsOptions) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    // A symlink is listed as the directory it points to.
+    if !metadata(dir).at("ls", dir)?.is_dir() {
+        err!(NotADirectory, "ls", dir)
     }
 
     let mut elements: Vec<PathBuf> = vec![];
 
-    for entry in read_dir(dir)? {
-        elements.push(entry?.path());
+    let entries = walk(dir)
+        .op("ls")
+        .follow_root(true)
+        .min_depth(1)
+        .max_depth(1)
+        .filter_entries(options.filter.clone());
+
+    for entry in entries {
+        elements.push(entry?.into_path());
     }
 
     Ok(elements)
 }
 
+/// Recursively walks the directory tree and lazily returns its entries
+/// (files, directories and symlinks) with their depth, type and metadata.
+/// `root` - the path to the directory whose tree you want to walk.
+///
+/// By default the root is returned first and every directory is returned
+/// before its contents. The walk can be adjusted with
+/// [`min_depth`](Walk::min_depth), [`max_depth`](Walk::max_depth) and
+/// [`contents_first`](Walk::contents_first), and descending into a directory
+/// can be skipped from inside the loop with
+/// [`skip_current_dir`](Walk::skip_current_dir). Symlinks are not followed
+/// unless [`follow_links`](Walk::follow_links) is enabled.
+///
+/// # Errors
+///
+/// The iterator returns an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - Param `root` contains file or directory does not exist.
+/// - The current process does not have the permission to access to some
+///   directory of the tree.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::walk;
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///
+///  let mut entries = walk(&dir).max_depth(3);
+///  while let Some(entry) = entries.next() {
+///      let entry = entry?;
+///      if entry.path().ends_with("target") {
+///          entries.skip_current_dir();
+///      }
+///  }
+/// ```
+pub fn walk(root: impl AsRef<Path>) -> Walk {
+    Walk::new(root.as_ref())
+}
+
 /// Deletes files/directories (including nested files/directories).
 /// `from` - takes a list of paths of what you want to delete.
+/// Symlinks are deleted themselves, their targets are left untouched.
 ///
 /// # Errors
 ///
@@ -72,7 +223,10 @@ pub fn ls(dir: &PathBuf) -> Result<Vec<PathBuf>> {
 /// - Param `from` contains file or directory does not exist.
 /// - Param `from` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `from` is empty.
+/// - Param `from` contains a root, the home directory or a mount point, see
+///   [`Guard`].
 ///
 /// # Example
 ///
@@ -80,29 +234,193 @@ pub fn ls(dir: &PathBuf) -> Result<Vec<PathBuf>> {
 ///  extern crate rfm;
 ///  use rfm::rm;
 ///
+///  rm(["./dir", "./file.txt"])?;
+/// ```
+pub fn rm(from: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    rm_with(from, &RemoveOptions::default())
+}
+
+/// Same as [`rm`], but deletes only the entries accepted by the filter from
+/// `options`. A directory is deleted only if the filter selects it or its
+/// content was deleted, and only if it's empty after that.
+/// Deleted entries can be reported with [`Progress`]. The paths are checked
+/// by the [`Guard`] from `options` before anything is deleted, with
+/// [`ErrorMode::Continue`] a protected path is reported in the
+/// [`BatchReport`] and the other paths are still deleted.
+///
+/// # Errors
+///
+/// See [`rm`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{rm_with, Filter, RemoveOptions};
+///
 ///  let dir = std::path::PathBuf::from(r"./dir");
-///  let file = std::path::PathBuf::from(r"./file.txt");
-///  let elements: Vec<&std::path::PathBuf> = vec![&dir, &file];
+///  let options = RemoveOptions::new().filter(Filter::new().include("*.tmp"));
 ///
-///  rm(&elements)?;
+///  rm_with(&vec![&dir], &options)?;
 /// ```
-pub fn rm(from: &Vec<&PathBuf>) -> Result<()> {
-    if from.len() <= 0 {
-        let err_msg = format!("from param is empty - {:?}", from);
-        err!(err_msg, ErrorKind::InvalidInput)
+pub fn rm_with(
+    from: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &RemoveOptions,
+) -> Result<()> {
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    rm_paths(&Disk, &utils::paths(from), options, &monitor)
+}
+
+/// Deletes the paths passed to [`rm_with`] or plans deleting them.
+pub(crate) fn rm_paths(
+    exec: &dyn Exec,
+    from: &[PathBuf],
+    options: &RemoveOptions,
+    monitor: &Monitor,
+) -> Result<()> {
+    if from.is_empty() {
+        err!(EmptyInput, "rm")
     }
 
-    for i in from {
-        if i.is_file() {
-            remove_file(i)?;
-        } else {
-            remove_dir_all(i)?;
-        }
+    // Protected paths are not scanned, they fail before they are touched.
+    monitor.start(
+        from.iter()
+            .filter(|path| options.guard.check("rm", path).is_ok())
+            .map(|path| walk(path).filter_entries(options.filter.clone())),
+        false,
+    );
+
+    batch::for_each_guarded("rm", from, options.on_error, &options.guard, |path| {
+        monitor.check(path, rm_root(exec, path, options, monitor))
+    })
+}
+
+/// Deletes a path passed to [`rm_with`].
+fn rm_root(exec: &dyn Exec, path: &Path, options: &RemoveOptions, monitor: &Monitor) -> Result<()> {
+    let threads = exec.threads(options.threads);
+    if threads > 1 {
+        return rm_root_parallel(exec, path, options, monitor, threads);
+    }
+
+    let entries = walk(path)
+        .op("rm")
+        .contents_first(true)
+        .filter_entries(options.filter.clone());
+    let remover = Remover::new(exec, path, options, monitor);
+
+    for entry in entries {
+        remover.rm_entry(&entry?)?;
     }
 
     Ok(())
 }
 
+/// Same as [`rm_root`], but deletes the subdirectories on `threads` threads.
+fn rm_root_parallel(
+    exec: &dyn Exec,
+    path: &Path,
+    options: &RemoveOptions,
+    monitor: &Monitor,
+    threads: usize,
+) -> Result<()> {
+    let root = walk(path)
+        .op("rm")
+        .max_depth(0)
+        .filter_entries(options.filter.clone())
+        .next();
+    let root = match root {
+        Some(root) => root?,
+        None => return Ok(()),
+    };
+    let remover = Remover::new(exec, path, options, monitor);
+    if !root.is_dir() {
+        return remover.rm_entry(&root);
+    }
+
+    let visit = |dir: &Subtree<()>, subdirs: &mut Vec<Subtree<()>>| {
+        let entries = dir
+            .walk(path)
+            .op("rm")
+            .filter_entries(options.filter.clone());
+
+        for entry in entries {
+            let entry = entry?;
+            if entry.is_dir() {
+                subdirs.push(dir.child(entry, ()));
+            } else {
+                remover.rm_entry(&entry)?;
+            }
+        }
+
+        Ok(())
+    };
+    let finish = |dir: Subtree<()>| remover.rm_entry(&dir.entry);
+
+    parallel::run(threads, Subtree::root(root, ()), visit, finish)
+}
+
+/// State of [`rm_with`] for one of its paths, shared by the threads.
+struct Remover<'a> {
+    exec: &'a dyn Exec,
+    root: &'a Path,
+    options: &'a RemoveOptions,
+    monitor: &'a Monitor<'a>,
+    // Directories content was deleted from, only tracked with a filter.
+    emptied: Mutex<HashSet<PathBuf>>,
+}
+
+impl<'a> Remover<'a> {
+    fn new(
+        exec: &'a dyn Exec,
+        root: &'a Path,
+        options: &'a RemoveOptions,
+        monitor: &'a Monitor<'a>,
+    ) -> Remover<'a> {
+        Remover {
+            exec,
+            root,
+            options,
+            monitor,
+            emptied: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Deletes a file, or a directory after its content. With a filter, a
+    /// directory is deleted only if the filter selects it or its content
+    /// was deleted by this call, and only if it's empty.
+    fn rm_entry(&self, entry: &Entry) -> Result<()> {
+        let (exec, filter, monitor) = (self.exec, &self.options.filter, self.monitor);
+        monitor.check_cancel("rm", entry.path())?;
+        if !filter.is_empty()
+            && entry.is_dir()
+            && !filter.includes_dir(self.root, entry)
+            && !self.emptied.lock().unwrap().contains(entry.path())
+        {
+            return Ok(());
+        }
+
+        monitor.entry_started(entry.path());
+        if entry.is_dir() {
+            match exec.remove_dir(entry.path()) {
+                Err(e) if !filter.is_empty() && e.kind() == ErrorKind::DirectoryNotEmpty => {
+                    monitor.entry_finished(entry.path());
+                    return Ok(());
+                }
+                res => res.at("rm", entry.path())?,
+            }
+        } else {
+            exec.remove_file(entry.path()).at("rm", entry.path())?;
+        }
+        if !filter.is_empty() {
+            let parent = entry.path().parent().map(Path::to_path_buf);
+            self.emptied.lock().unwrap().extend(parent);
+        }
+        monitor.entry_finished(entry.path());
+
+        Ok(())
+    }
+}
+
 /// Creates a file/files in the passed path.
 /// `file_paths` - takes a list of paths of what you want to create.
 ///
@@ -114,7 +432,13 @@ pub fn rm(from: &Vec<&PathBuf>) -> Result<()> {
 /// - Param `file_paths` contains file or directory does not exist.
 /// - Param `file_paths` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `file_paths` is empty.
+///
+/// With several paths, the function stops at the first one that fails and
+/// returns its error, the paths after it are not created. Earlier versions
+/// tried every path and ignored the errors, see [`ErrorMode::Continue`] to
+/// try every path and get the failed ones.
 ///
 /// # Example
 ///
@@ -122,19 +446,38 @@ pub fn rm(from: &Vec<&PathBuf>) -> Result<()> {
 ///  extern crate rfm;
 ///  use rfm::touch;
 ///
-///  let file_1 = std::path::PathBuf::from(r"./file-1.txt");
-///  let file_2 = std::path::PathBuf::from(r"./file-2.txt");
-///  let files: Vec<&std::path::PathBuf> = vec![&file_1, &file_2];
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let files = ["file-1.txt", "file-2.txt"].iter().map(|name| dir.join(name));
 ///
-///  touch(&files)?;
+///  touch(files)?;
 /// ```
-pub fn touch(file_paths: &Vec<&PathBuf>) -> Result<()> {
-    if file_paths.len() <= 0 {
-        let err_msg = format!("file_paths param is empty - {:?}", file_paths);
-        err!(err_msg, ErrorKind::InvalidInput)
-    }
+pub fn touch(file_paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    touch_with(file_paths, &TouchOptions::default())
+}
 
-    Ok(utils::create(file_paths, &utils::CreateType::File)?)
+/// Same as [`touch`], but with `options`: whether existing files are
+/// truncated and whether missing parent directories are created.
+///
+/// # Errors
+///
+/// See [`touch`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{touch_with, TouchOptions};
+///
+///  let options = TouchOptions::new().truncate(false).parents(true);
+///
+///  touch_with(["./logs/app.log"], &options)?;
+/// ```
+pub fn touch_with(
+    file_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &TouchOptions,
+) -> Result<()> {
+    let file_paths = utils::paths(file_paths);
+    utils::create(&Disk, &file_paths, &utils::CreateType::File(options))
 }
 
 /// Creates a directory/directories on the passed path. **Note**, the function
@@ -149,7 +492,13 @@ pub fn touch(file_paths: &Vec<&PathBuf>) -> Result<()> {
 /// - Param `dir_paths` contains file or directory does not exist.
 /// - Param `dir_paths` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `dir_paths` is empty.
+///
+/// With several paths, the function stops at the first one that fails and
+/// returns its error, the paths after it are not created. Earlier versions
+/// tried every path and ignored the errors, see [`ErrorMode::Continue`] to
+/// try every path and get the failed ones.
 ///
 /// # Example
 ///
@@ -159,17 +508,37 @@ pub fn touch(file_paths: &Vec<&PathBuf>) -> Result<()> {
 ///
 ///  let dir_1 = std::path::PathBuf::from(r"./dir");
 ///  let dir_2 = std::path::PathBuf::from(r"./dir2");
-///  let dirs: Vec<&std::path::PathBuf> = vec![&dir_1, &dir_2];
 ///
-///  mkdir(&dirs)?;
+///  mkdir([&dir_1, &dir_2])?;
 /// ```
-pub fn mkdir(dir_paths: &Vec<&PathBuf>) -> Result<()> {
-    if dir_paths.len() <= 0 {
-        let err_msg = format!("dir_paths param is empty - {:?}", dir_paths);
-        err!(err_msg, ErrorKind::InvalidInput)
-    }
+pub fn mkdir(dir_paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    mkdir_with(dir_paths, &MkdirOptions::default())
+}
 
-    Ok(utils::create(dir_paths, &utils::CreateType::Dir)?)
+/// Same as [`mkdir`], but with `options`: whether missing parent directories
+/// are created, whether existing directories are accepted and the
+/// permissions of the created directories.
+///
+/// # Errors
+///
+/// See [`mkdir`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{mkdir_with, MkdirOptions};
+///
+///  let options = MkdirOptions::new().exist_ok(true).mode(0o700);
+///
+///  mkdir_with(["./cache"], &options)?;
+/// ```
+pub fn mkdir_with(
+    dir_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &MkdirOptions,
+) -> Result<()> {
+    let dir_paths = utils::paths(dir_paths);
+    utils::create(&Disk, &dir_paths, &utils::CreateType::Dir(options))
 }
 
 /// Copies files and directories, including nested files and directories.
@@ -184,7 +553,12 @@ pub fn mkdir(dir_paths: &Vec<&PathBuf>) -> Result<()> {
 /// - Param `from` contains file or directory does not exist.
 /// - Param `from` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `from` is empty.
+/// - Param `to` does not exist or isn't a directory, see
+///   [`CopyOptions::create_target`] and
+///   [`CopyOptions::no_target_directory`].
+/// - Param `to` is a directory of `from` or inside one.
 ///
 /// # Example
 ///
@@ -192,54 +566,77 @@ pub fn mkdir(dir_paths: &Vec<&PathBuf>) -> Result<()> {
 ///  extern crate rfm;
 ///  use rfm::cp;
 ///
-///  let file = std::path::PathBuf::from(r"./file.txt");
+///  cp(["./file.txt", "./dir"], "./to")?;
+/// ```
+pub fn cp(from: impl IntoIterator<Item = impl AsRef<Path>>, to: impl AsRef<Path>) -> Result<()> {
+    cp_with(from, to, &CopyOptions::default())
+}
+
+/// Same as [`cp`], but with `options`: a filter for the copied entries,
+/// a [`ConflictPolicy`] for entries whose destination already exists and
+/// a [`Progress`] callback.
+///
+/// # Errors
+///
+/// See [`cp`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{cp_with, ConflictPolicy, CopyOptions, Filter};
+///
 ///  let dir = std::path::PathBuf::from(r"./dir");
-///  let elements: Vec<&std::path::PathBuf> = vec![&file, &dir];
 ///  let to = std::path::PathBuf::from(r"./to");
+///  let options = CopyOptions::new()
+///      .filter(Filter::new().exclude("target/"))
+///      .conflict(ConflictPolicy::OverwriteIfNewer);
 ///
-///  cp(&elements, &to)?;
+///  cp_with(&vec![&dir], &to, &options)?;
 /// ```
-pub fn cp(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
-    if from.len() <= 0 {
-        let err_msg = format!("from param is empty - {:?}", from);
-        err!(err_msg, ErrorKind::InvalidInput)
-    }
-
-    if from.len() == 1 && from[0].is_file() {
-        let file_name = &from[0].file_name().unwrap();
-        let path_to = &to.join(file_name);
+pub fn cp_with(
+    from: impl IntoIterator<Item = impl AsRef<Path>>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<()> {
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    cp_paths(&Disk, &utils::paths(from), to.as_ref(), options, &monitor)
+}
 
-        let copy_res = copy(&from[0], &path_to);
+/// Copies the paths passed to [`cp_with`] or plans copying them.
+pub(crate) fn cp_paths(
+    exec: &dyn Exec,
+    from: &[PathBuf],
+    to: &Path,
+    options: &CopyOptions,
+    monitor: &Monitor,
+) -> Result<()> {
+    if from.is_empty() {
+        err!(EmptyInput, "cp")
+    }
 
-        match copy_res {
-            Ok(..) => Ok(()),
-            Err(_error) => err!(_error, ErrorKind::InvalidInput),
-        }
-    } else {
-        for path in from {
-            if path.is_file() {
-                let from: Vec<&PathBuf> = vec![path];
-                cp(&from, &to)?;
-            } else {
-                let dir_name = path.file_name().unwrap();
-                let path_to = &to.join(dir_name);
-                create_dir(&path_to)?;
+    monitor.start(from.iter().map(|path| tree(path, options)), false);
 
-                for i in ls(&path).unwrap() {
-                    let from: Vec<&PathBuf> = vec![&i];
-                    cp(&from, &path_to)?;
-                }
-            }
-        }
-
-        Ok(())
+    if let Some(dir) = transfer::check_target("cp", from, to, options)? {
+        exec::create_dir_all(exec, "cp", &dir)?;
     }
+
+    batch::for_each("cp", from, options.on_error, |path| {
+        let path_to = transfer::destination(path, to, options);
+        transfer::check_inside("cp", path, &path_to, options)?;
+        let res = transfer::copy_tree(exec, "cp", path, &path_to, options, monitor);
+        monitor.check(path, res)
+    })
 }
 
 /// Moves files and directories, including nested files and directories.
 /// `from` - takes a list of paths of what you want to copy.
 /// `to` - destination path.
 ///
+/// Entries are renamed if possible, so moving within one filesystem doesn't
+/// copy any data. Entries on another filesystem are copied and deleted
+/// afterwards.
+///
 /// # Errors
 ///
 /// This function will return an error in the following situations, but is not
@@ -248,7 +645,14 @@ pub fn cp(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 /// - Param `from` contains file or directory does not exist.
 /// - Param `from` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `from` is empty.
+/// - Param `to` does not exist or isn't a directory, see
+///   [`CopyOptions::create_target`] and
+///   [`CopyOptions::no_target_directory`].
+/// - Param `to` is a directory of `from` or inside one.
+/// - Param `from` contains a root, the home directory or a mount point, see
+///   [`Guard`].
 ///
 /// # Example
 ///
@@ -256,31 +660,148 @@ pub fn cp(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 ///  extern crate rfm;
 ///  use rfm::mv;
 ///
-///  let file = std::path::PathBuf::from(r"./file.txt");
+///  let file = std::path::Path::new(r"./file.txt");
+///
+///  mv([file], "./to")?;
+/// ```
+pub fn mv(from: impl IntoIterator<Item = impl AsRef<Path>>, to: impl AsRef<Path>) -> Result<()> {
+    mv_with(from, to, &CopyOptions::default()).map(|_| ())
+}
+
+/// Same as [`mv`], but with `options`: a filter for the moved entries and
+/// a [`ConflictPolicy`] for entries whose destination already exists.
+/// Skipped entries stay at the starting point. Returns a [`MoveReport`]
+/// with the moved entries and the [`MoveStrategy`] used for each of them.
+///
+/// A copy that fails midway is deleted, so no partial copies are left at the
+/// destination. With [`CopyOptions::rollback`] everything moved by the call
+/// is moved back on failure, and overwritten destinations are put back. The
+/// paths are checked by the [`Guard`] from
+/// `options` before anything is moved.
+///
+/// # Errors
+///
+/// See [`mv`]. The error carries a [`MoveReport`] with the [`MoveStatus`] of
+/// every source path: moved, partially moved or untouched, see
+/// [`Error::report`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{mv_with, ConflictPolicy, CopyOptions};
+///
 ///  let dir = std::path::PathBuf::from(r"./dir");
-///  let elements: Vec<&std::path::PathBuf> = vec![&file, &dir];
 ///  let to = std::path::PathBuf::from(r"./to");
+///  let options = CopyOptions::new().conflict(ConflictPolicy::Skip);
 ///
-///  mv(&elements, &to)?;
+///  let report = mv_with(&vec![&dir], &to, &options)?;
+///  for moved in report.moved() {
+///      println!("{:?}: {:?}", moved.from_path(), moved.strategy());
+///  }
 /// ```
-pub fn mv(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
-    if from.len() <= 0 {
-        let err_msg = format!("from param is empty - {:?}", from);
-        err!(err_msg, ErrorKind::InvalidInput)
+pub fn mv_with(
+    from: impl IntoIterator<Item = impl AsRef<Path>>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<MoveReport> {
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    mv_paths(&Disk, &utils::paths(from), to.as_ref(), options, &monitor)
+}
+
+/// Moves the paths passed to [`mv_with`] or plans moving them.
+pub(crate) fn mv_paths(
+    exec: &dyn Exec,
+    from: &[PathBuf],
+    to: &Path,
+    options: &CopyOptions,
+    monitor: &Monitor,
+) -> Result<MoveReport> {
+    if from.is_empty() {
+        err!(EmptyInput, "mv")
+    }
+    for path in from {
+        options.guard.check("mv", path)?;
+    }
+    if let Some(dir) = transfer::check_target("mv", from, to, options)? {
+        exec::create_dir_all(exec, "mv", &dir)?;
     }
 
-    let copy = cp(&from, &to);
+    let options = &options.clone().preserve(Preserve::all());
+    let mut report = MoveReport::default();
+    monitor.start(from.iter().map(|path| tree(path, options)), false);
+    let mover = transfer::Mover {
+        exec,
+        op: "mv",
+        options,
+        monitor,
+    };
+
+    // Sources after a failed one are reported as untouched.
+    for path in from {
+        report.add_source(path);
+    }
+
+    for (i, path) in from.iter().enumerate() {
+        report.begin(i);
+        let res = move_root(&mover, path, to, &mut report);
+        let res = monitor.check(path, res);
+        report.finish(i, res.is_ok());
+
+        if let Err(e) = res {
+            // A failed rollback is reported by the status of the sources.
+            if options.rollback {
+                transfer::rollback(exec, "mv", &mut report).ok();
+            }
+            transfer::drop_replaced(exec, "mv", &report).ok();
+            return Err(e.with_report(report));
+        }
+    }
+
+    transfer::drop_replaced(exec, "mv", &report)?;
+    Ok(report)
+}
+
+/// Returns the walk over the entries copied or moved from `path`, for the
+/// prescan.
+fn tree(path: &Path, options: &CopyOptions) -> Walk {
+    walk(path)
+        .follow_links(options.symlinks == SymlinkPolicy::Follow)
+        .filter_entries(options.filter.clone())
+}
 
-    match copy {
-        Ok(_res) => rm(&from),
-        Err(error) => err!(format!("Error when copying: {}", error)),
+/// Moves a source path passed to [`mv_with`] into the `to` directory.
+fn move_root(
+    mover: &transfer::Mover,
+    path: &Path,
+    to: &Path,
+    report: &mut MoveReport,
+) -> Result<()> {
+    let options = mover.options;
+    if walk(path)
+        .op("mv")
+        .max_depth(0)
+        .filter_entries(options.filter.clone())
+        .next()
+        .transpose()?
+        .is_none()
+    {
+        return Ok(());
     }
+
+    let path_to = transfer::destination(path, to, options);
+    transfer::check_inside("mv", path, &path_to, options)?;
+    mover.move_entry(path, path, &path_to, report)
 }
 
 /// Clears the directory/directories of all child files and directories on the
 /// passed path.
 /// `paths` - takes a list of paths of what you want to clean.
 ///
+/// Only the children are deleted: the directory itself stays in place with
+/// its permissions, ownership and attributes, so open handles and watches on
+/// it keep working.
+///
 /// # Errors
 ///
 /// This function will return an error in the following situations, but is not
@@ -288,8 +809,12 @@ pub fn mv(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 ///
 /// - Param `paths` contains file or directory does not exist.
 /// - Param `paths` contains file or directory with invalid name.
+/// - Param `paths` contains a path that isn't a directory.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `paths` is empty.
+/// - Param `paths` contains a root, the home directory or a mount point, see
+///   [`Guard`].
 ///
 /// # Example
 ///
@@ -299,33 +824,112 @@ pub fn mv(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 ///
 ///  let dir_1 = std::path::PathBuf::from(r"./dir1");
 ///  let dir_2 = std::path::PathBuf::from(r"./dir2");
-///  let dirs: Vec<&std::path::PathBuf> = vec![&dir_1, &dir_2];
 ///
-///  clean(&dirs)?;
+///  clean(vec![dir_1, dir_2])?;
 /// ```
-pub fn clean(paths: &Vec<&PathBuf>) -> Result<()> {
-    if paths.len() <= 0 {
-        let err_msg = format!("paths param is empty - {:?}", paths);
-        err!(err_msg, ErrorKind::InvalidInput)
+pub fn clean(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    clean_with(paths, &CleanOptions::default())
+}
+
+/// Same as [`clean`], but keeps the entries matching the patterns from
+/// `options` and, if an age is set, the entries modified more recently.
+/// Directories still containing kept entries are kept too. The paths are
+/// checked by the [`Guard`] from `options` before anything is deleted, with
+/// [`ErrorMode::Continue`] a protected path is reported in the
+/// [`BatchReport`] and the other paths are still cleaned.
+///
+/// # Errors
+///
+/// See [`clean`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{clean_with, CleanOptions};
+///  use std::time::Duration;
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let options = CleanOptions::new()
+///      .keep(".gitkeep")
+///      .older_than(Duration::from_secs(7 * 24 * 60 * 60));
+///
+///  clean_with(&vec![&dir], &options)?;
+/// ```
+pub fn clean_with(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &CleanOptions,
+) -> Result<()> {
+    clean_paths(&Disk, &utils::paths(paths), options)
+}
+
+/// Cleans the paths passed to [`clean_with`] or plans cleaning them.
+pub(crate) fn clean_paths(
+    exec: &dyn Exec,
+    paths: &[PathBuf],
+    options: &CleanOptions,
+) -> Result<()> {
+    if paths.is_empty() {
+        err!(EmptyInput, "clean")
     }
 
-    if paths.len() == 1 {
-        rm(&paths)?;
-        let vec_of_paths = vec![paths[0]];
-        Ok(mkdir(&vec_of_paths)?)
-    } else {
-        for i in paths {
-            let elements: Vec<&PathBuf> = vec![i];
-            clean(&elements)?;
+    batch::for_each_guarded("clean", paths, options.on_error, &options.guard, |path| {
+        clean_root(exec, path, options)
+    })
+}
+
+/// Deletes the content of a directory passed to [`clean_with`].
+fn clean_root(exec: &dyn Exec, path: &Path, options: &CleanOptions) -> Result<()> {
+    for entry in clean_entries(path, options)? {
+        let entry = entry?;
+        if entry.is_dir() {
+            match exec.remove_dir(entry.path()) {
+                Err(e) if options.is_partial() && e.kind() == ErrorKind::DirectoryNotEmpty => {}
+                res => res.at("clean", entry.path())?,
+            }
+        } else {
+            exec.remove_file(entry.path()).at("clean", entry.path())?;
         }
-        Ok(())
     }
+
+    Ok(())
+}
+
+/// Returns the children of the directory `path` to delete with [`clean_with`],
+/// content first.
+fn clean_entries<'a>(
+    path: &Path,
+    options: &'a CleanOptions,
+) -> Result<impl Iterator<Item = Result<Entry>> + 'a> {
+    if !std::fs::symlink_metadata(path).at("clean", path)?.is_dir() {
+        err!(NotADirectory, "clean", path)
+    }
+
+    // New directories are descended into, as they may contain old files.
+    let age = options.clone();
+    let filter = options
+        .keep
+        .clone()
+        .predicate(move |entry| entry.is_dir() || age.is_old(entry));
+    let entries = walk(path)
+        .op("clean")
+        .min_depth(1)
+        .contents_first(true)
+        .filter_entries(filter);
+
+    Ok(entries.filter(move |entry| match entry {
+        Ok(entry) if entry.is_dir() => options.is_old(entry),
+        _ => true,
+    }))
 }
 
 /// Extracts all files from the directory, including nested files.
 /// `from` - takes a list of paths of where you want to extract files from.
 /// `to` - destination path.
 ///
+/// A symlink to a directory is extracted like the directory. Other symlinks
+/// inside are handled by [`ExtractOptions::symlinks`].
+///
 /// # Errors
 ///
 /// This function will return an error in the following situations, but is not
@@ -334,7 +938,8 @@ pub fn clean(paths: &Vec<&PathBuf>) -> Result<()> {
 /// - Param `from` contains file or directory does not exist.
 /// - Param `from` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
+/// - Param `from` is empty.
 ///
 /// # Example
 ///
@@ -342,32 +947,250 @@ pub fn clean(paths: &Vec<&PathBuf>) -> Result<()> {
 ///  extern crate rfm;
 ///  use rfm::extract;
 ///
+///  let to = std::path::PathBuf::from(r"./to");
+///
+///  extract(["./dir", "./dir2"], &to)?;
+/// ```
+pub fn extract(
+    from: impl IntoIterator<Item = impl AsRef<Path>>,
+    to: impl AsRef<Path>,
+) -> Result<()> {
+    extract_with(from, to, &ExtractOptions::default())
+}
+
+/// Same as [`extract`], but with `options`: a filter for the extracted files,
+/// a [`Naming`] strategy for files with the same name, a [`ConflictPolicy`]
+/// for files whose destination already exists and a [`Progress`] callback.
+/// With [`ExtractOptions::move_files`] the files are moved instead, and the
+/// directories emptied by that are deleted.
+///
+/// # Errors
+///
+/// See [`extract`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{extract_with, ExtractOptions, Filter};
+///
 ///  let dir = std::path::PathBuf::from(r"./dir");
-///  let dir_2 = std::path::PathBuf::from(r"./dir2");
-///  let dirs: Vec<&std::path::PathBuf> = vec![&dir, &dir_2];
 ///  let to = std::path::PathBuf::from(r"./to");
+///  let options = ExtractOptions::new().filter(Filter::new().include("*.jpg"));
 ///
-///  extract(&dirs, &to)?;
+///  extract_with(&vec![&dir], &to, &options)?;
 /// ```
-pub fn extract(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
-    if from.len() <= 0 {
-        let err_msg = format!("from param is empty - {:?}", from);
-        err!(err_msg, ErrorKind::InvalidInput)
+pub fn extract_with(
+    from: impl IntoIterator<Item = impl AsRef<Path>>,
+    to: impl AsRef<Path>,
+    options: &ExtractOptions,
+) -> Result<()> {
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    extract_paths(&Disk, &utils::paths(from), to.as_ref(), options, &monitor)
+}
+
+/// Extracts the paths passed to [`extract_with`] or plans extracting them.
+pub(crate) fn extract_paths(
+    exec: &dyn Exec,
+    from: &[PathBuf],
+    to: &Path,
+    options: &ExtractOptions,
+    monitor: &Monitor,
+) -> Result<()> {
+    if from.is_empty() {
+        err!(EmptyInput, "extract")
+    }
+
+    let files = |path: &PathBuf| {
+        walk(path)
+            .min_depth(1)
+            .follow_links(options.symlinks == SymlinkPolicy::Follow)
+            .filter_entries(options.filter.clone())
+    };
+    monitor.start(from.iter().map(files), true);
+
+    let extractor = Extractor {
+        exec,
+        options,
+        monitor,
+        namer: Mutex::new(Namer::new(options.naming)),
+        claims: Claims::default(),
+        emptied: Mutex::new(HashSet::new()),
+    };
+    batch::for_each("extract", from, options.on_error, |path| {
+        monitor.check(path, extractor.extract_root(path, to))
+    })
+}
+
+/// State of [`extract_with`] shared by the roots and the threads.
+struct Extractor<'a> {
+    exec: &'a dyn Exec,
+    options: &'a ExtractOptions,
+    monitor: &'a Monitor<'a>,
+    // The names are shared by all the roots.
+    namer: Mutex<Namer>,
+    claims: Claims,
+    // Directories of the current root files were moved out of.
+    emptied: Mutex<HashSet<PathBuf>>,
+}
+
+impl Extractor<'_> {
+    /// Extracts the files of a directory passed to [`extract_with`].
+    fn extract_root(&self, path: &Path, to: &Path) -> Result<()> {
+        if !path.is_dir() {
+            err!(NotADirectory, "extract", path)
+        }
+
+        let threads = self.exec.threads(self.options.threads);
+        if threads > 1 {
+            self.extract_parallel(path, to, threads)?;
+        } else {
+            let entries = walk(path)
+                .op("extract")
+                .follow_root(true)
+                .min_depth(1)
+                .follow_links(self.options.symlinks == SymlinkPolicy::Follow)
+                .filter_entries(self.options.filter.clone());
+
+            for entry in entries {
+                let entry = entry?;
+                if !entry.is_dir() {
+                    self.extract_file(path, &entry, to)?;
+                }
+            }
+        }
+
+        let emptied = std::mem::take(&mut *self.emptied.lock().unwrap());
+        if self.options.move_files {
+            remove_emptied(self.exec, path, emptied, self.options.remove_root)?;
+        }
+
+        Ok(())
     }
 
-    for i in from {
-        let paths = ls(i)?;
+    /// Same as the sequential walk of [`extract_root`](Extractor::extract_root),
+    /// but extracts the subdirectories on `threads` threads.
+    fn extract_parallel(&self, path: &Path, to: &Path, threads: usize) -> Result<()> {
+        let follow_links = self.options.symlinks == SymlinkPolicy::Follow;
+        let root = walk(path)
+            .op("extract")
+            .follow_root(true)
+            .max_depth(0)
+            .follow_links(follow_links)
+            .next();
+        let root = match root {
+            Some(root) => root?,
+            None => return Ok(()),
+        };
 
-        if paths.len() > 0 {
-            for p in paths {
-                if p.is_file() {
-                    let file = vec![&p];
-                    cp(&file, &to)?;
+        let visit = |dir: &Subtree<()>, subdirs: &mut Vec<Subtree<()>>| {
+            let entries = dir
+                .walk(path)
+                .op("extract")
+                .follow_links(follow_links)
+                .filter_entries(self.options.filter.clone());
+
+            for entry in entries {
+                let entry = entry?;
+                if entry.is_dir() {
+                    subdirs.push(dir.child(entry, ()));
                 } else {
-                    let newfrom = vec![&p];
-                    extract(&newfrom, &to)?;
+                    self.extract_file(path, &entry, to)?;
                 }
             }
+
+            Ok(())
+        };
+
+        parallel::run(threads, Subtree::root(root, ()), visit, |_| Ok(()))
+    }
+
+    /// Copies or moves a file of the extracted directory `root` into `to`.
+    fn extract_file(&self, root: &Path, entry: &Entry, to: &Path) -> Result<()> {
+        let options = self.options;
+        self.monitor.check_cancel("extract", entry.path())?;
+        if !transfer::accepts_symlink("extract", options.symlinks, entry)? {
+            return Ok(());
+        }
+
+        let named = self
+            .namer
+            .lock()
+            .unwrap()
+            .name(self.exec, "extract", root, entry, to)?;
+        let entry_to = match named {
+            Some(entry_to) => entry_to,
+            None if options.move_files => {
+                // The same content is already at the destination.
+                self.exec
+                    .remove_file(entry.path())
+                    .at("extract", entry.path())?;
+                self.emptied_parent(entry);
+                return Ok(());
+            }
+            None => return Ok(()),
+        };
+
+        let _claim = self.claims.claim(&entry_to);
+        let target = transfer::target(
+            self.exec,
+            "extract",
+            &options.conflict,
+            entry.path(),
+            entry.metadata(),
+            entry_to,
+        )?;
+
+        // Files replace existing ones only once they are complete.
+        if let transfer::Target::Create(entry_to) | transfer::Target::Replace(entry_to) = target {
+            self.monitor.entry_started(entry.path());
+            let (exec, reflink) = (self.exec, options.reflink);
+            if options.move_files {
+                transfer::move_file(exec, "extract", entry, &entry_to, reflink, self.monitor)?;
+                self.emptied_parent(entry);
+            } else {
+                exec.copy_file("extract", entry, &entry_to, reflink, self.monitor)?;
+                let (path, meta) = (entry.path(), entry.metadata());
+                exec.preserve("extract", options.preserve, path, meta, &entry_to)?;
+            }
+            self.monitor.entry_finished(entry.path());
+        }
+
+        Ok(())
+    }
+
+    fn emptied_parent(&self, entry: &Entry) {
+        let parent = entry.path().parent().map(Path::to_path_buf);
+        self.emptied.lock().unwrap().extend(parent);
+    }
+}
+
+/// Deletes the directories of `root` that are empty after moving the files
+/// out of the `emptied` directories, content first. `root` itself is deleted
+/// only if `with_root` is set.
+fn remove_emptied(
+    exec: &dyn Exec,
+    root: &Path,
+    mut emptied: HashSet<PathBuf>,
+    with_root: bool,
+) -> Result<()> {
+    let dirs = walk(root)
+        .op("extract")
+        .follow_root(true)
+        .min_depth(if with_root { 0 } else { 1 })
+        .contents_first(true);
+
+    for dir in dirs {
+        let dir = dir?;
+        // A symlinked root is kept, only the directory it points to is
+        // emptied.
+        if !dir.is_dir() || dir.path_is_symlink() || !emptied.contains(dir.path()) {
+            continue;
+        }
+        match exec.remove_dir(dir.path()) {
+            Ok(()) => emptied.extend(dir.path().parent().map(Path::to_path_buf)),
+            Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => {}
+            Err(e) => return Err(e).at("extract", dir.path()),
         }
     }
 
@@ -376,6 +1199,7 @@ pub fn extract(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 
 /// Returns the size of a file or directory in bytes
 /// `path` - the path to the directory/file whose size you want to get.
+/// Symlinks are not followed.
 ///
 /// # Errors
 ///
@@ -385,7 +1209,7 @@ pub fn extract(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 /// - Param `path` contains file or directory does not exist.
 /// - Param `path` contains file or directory with invalid name.
 /// - The current process does not have the permission to access to input
-/// params.
+///   params.
 ///
 /// # Example
 ///
@@ -399,19 +1223,230 @@ pub fn extract(from: &Vec<&PathBuf>, to: &PathBuf) -> Result<()> {
 ///  let file = std::path::PathBuf::from(r"./dir1/file.txt");
 ///  let file_size = get_size(&file);
 /// ```
-pub fn get_size(path: &PathBuf) -> Result<u64> {
-    let mut result = 0;
-
-    if path.is_dir() {
-        for entry in read_dir(&path)? {
-            let _entry_item = entry?.path();
-            result += _entry_item.metadata()?.len();
-            if _entry_item.is_dir() {
-                result += get_size(&_entry_item)?;
-            }
+pub fn get_size(path: impl AsRef<Path>) -> Result<u64> {
+    get_size_with(path, &SizeOptions::default())
+}
+
+/// Same as [`get_size`], but counts only the entries accepted by the filter
+/// from `options`, measured by the [`SizeMode`] from `options`. Counted
+/// entries can be reported with [`Progress`].
+///
+/// # Errors
+///
+/// See [`get_size`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{get_size_with, Filter, SizeOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./dir1");
+///  let options = SizeOptions::new().filter(Filter::new().exclude(".git/"));
+///
+///  let dir_size = get_size_with(&dir, &options)?;
+/// ```
+pub fn get_size_with(path: impl AsRef<Path>, options: &SizeOptions) -> Result<u64> {
+    get_usage_with(path, options).map(|usage| usage.bytes())
+}
+
+/// Returns the size of a file or directory with the number of files,
+/// directories and symlinks in it.
+/// `path` - the path to the directory/file whose usage you want to get.
+/// Symlinks are not followed.
+///
+/// # Errors
+///
+/// See [`get_size`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::get_usage;
+///
+///  let dir = std::path::PathBuf::from(r"./dir1");
+///  let usage = get_usage(&dir)?;
+///
+///  println!("{} bytes in {} files", usage.bytes(), usage.files());
+/// ```
+pub fn get_usage(path: impl AsRef<Path>) -> Result<Usage> {
+    get_usage_with(path, &SizeOptions::default())
+}
+
+/// Same as [`get_usage`], but with `options`, see [`get_size_with`].
+///
+/// # Errors
+///
+/// See [`get_size`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{get_usage_with, SizeMode, SizeOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./dir1");
+///  let options = SizeOptions::new()
+///      .mode(SizeMode::DiskUsage)
+///      .dedupe_hard_links(true);
+///
+///  let usage = get_usage_with(&dir, &options)?;
+/// ```
+pub fn get_usage_with(path: impl AsRef<Path>, options: &SizeOptions) -> Result<Usage> {
+    let path = path.as_ref();
+    // Counting the size is a scan itself, so the totals aren't known.
+    options.progress.emit(Event::Started {
+        total_bytes: None,
+        total_entries: None,
+    });
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    monitor.check(path, usage(path, options, &monitor))
+}
+
+/// Adds up the entries for [`get_usage_with`].
+fn usage(path: &Path, options: &SizeOptions, monitor: &Monitor) -> Result<Usage> {
+    let threads = parallel::threads(options.threads);
+    if threads > 1 {
+        return usage_parallel(path, options, monitor, threads);
+    }
+
+    let mut counter = Counter::new(options.mode, options.dedupe_hard_links);
+
+    for entry in walk(path)
+        .op("get_size")
+        .filter_entries(options.filter.clone())
+    {
+        let entry = entry?;
+        monitor.check_cancel("get_size", entry.path())?;
+        counter.add(&entry);
+        monitor.entry_finished(entry.path());
+    }
+
+    Ok(counter.usage())
+}
+
+/// Same as [`usage`], but walks the subdirectories on `threads` threads.
+fn usage_parallel(
+    path: &Path,
+    options: &SizeOptions,
+    monitor: &Monitor,
+    threads: usize,
+) -> Result<Usage> {
+    let counter = Mutex::new(Counter::new(options.mode, options.dedupe_hard_links));
+    let count = |entry: &Entry| -> Result<()> {
+        monitor.check_cancel("get_size", entry.path())?;
+        counter.lock().unwrap().add(entry);
+        monitor.entry_finished(entry.path());
+        Ok(())
+    };
+
+    let root = walk(path)
+        .op("get_size")
+        .max_depth(0)
+        .filter_entries(options.filter.clone())
+        .next();
+    if let Some(root) = root {
+        let root = root?;
+        count(&root)?;
+        if root.is_dir() {
+            let visit = |dir: &Subtree<()>, subdirs: &mut Vec<Subtree<()>>| {
+                let entries = dir
+                    .walk(path)
+                    .op("get_size")
+                    .filter_entries(options.filter.clone());
+
+                for entry in entries {
+                    let entry = entry?;
+                    count(&entry)?;
+                    if entry.is_dir() {
+                        subdirs.push(dir.child(entry, ()));
+                    }
+                }
+
+                Ok(())
+            };
+            parallel::run(threads, Subtree::root(root, ()), visit, |_| Ok(()))?;
         }
-    } else {
-        result = path.metadata()?.len();
     }
-    Ok(result)
+
+    let usage = counter.lock().unwrap().usage();
+    Ok(usage)
+}
+
+/// Returns the tree of the directories down to `depth` below `path`, with
+/// the size and the number of files of every one of them. The tree is built
+/// in a single walk, and can be sorted to find the largest directories.
+/// `path` - the path to the directory whose usage you want to get.
+/// `depth` - the depth of the returned tree, `0` for the directory only.
+///
+/// # Errors
+///
+/// See [`get_size`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::du;
+///
+///  let dir = std::path::PathBuf::from(r"./cache");
+///  let tree = du(&dir, 3)?;
+///
+///  let mut dirs = tree.nodes();
+///  dirs.sort_by_key(|node| std::cmp::Reverse(node.size()));
+///  for node in dirs.iter().skip(1).take(20) {
+///      println!("{} {:?}", node.size(), node.path());
+///  }
+/// ```
+pub fn du(path: impl AsRef<Path>, depth: usize) -> Result<DuNode> {
+    du_with(path, depth, &SizeOptions::default())
+}
+
+/// Same as [`du`], but with `options`, see [`get_size_with`]. Use
+/// [`SizeMode::DiskUsage`] to get the numbers of the `du` command.
+///
+/// # Errors
+///
+/// See [`get_size`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{du_with, SizeMode, SizeOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./cache");
+///  let options = SizeOptions::new().mode(SizeMode::DiskUsage);
+///
+///  let mut tree = du_with(&dir, 1, &options)?;
+///  tree.sort_by_size();
+/// ```
+pub fn du_with(path: impl AsRef<Path>, depth: usize, options: &SizeOptions) -> Result<DuNode> {
+    let path = path.as_ref();
+    options.progress.emit(Event::Started {
+        total_bytes: None,
+        total_entries: None,
+    });
+    let monitor = Monitor::new(&options.progress, options.cancel.as_ref());
+    monitor.check(path, du_tree(path, depth, options, &monitor))
+}
+
+/// Builds the tree for [`du_with`].
+fn du_tree(path: &Path, depth: usize, options: &SizeOptions, monitor: &Monitor) -> Result<DuNode> {
+    let mut counter = Counter::new(options.mode, options.dedupe_hard_links);
+    let mut tree = DuTree::new(depth);
+
+    for entry in walk(path).op("du").filter_entries(options.filter.clone()) {
+        let entry = entry?;
+        monitor.check_cancel("du", entry.path())?;
+        tree.add(&entry, counter.add(&entry));
+        monitor.entry_finished(entry.path());
+    }
+
+    match tree.finish() {
+        Some(root) => Ok(root),
+        // The root itself is excluded by the filter.
+        None => Ok(DuNode::new(path)),
+    }
 }
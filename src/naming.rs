@@ -0,0 +1,164 @@
+use crate::{
+    conflict::is_different,
+    error::{Result, ResultExt},
+    exec::Exec,
+    utils,
+    walk::Entry,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    ffi::OsString,
+    fs::File,
+    hash::Hasher,
+    io::{self, ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+/// Separator of the directories in the names made by
+/// [`Naming::PathEncoded`].
+const SEPARATOR: &str = "__";
+
+/// How [`extract_with`](crate::extract_with) names the extracted files, and
+/// what it does when two of them get the same name.
+///
+/// The strategies handle collisions between the files extracted by one call.
+/// Files that already existed at the destination are handled by the
+/// [`ConflictPolicy`](crate::ConflictPolicy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Naming {
+    /// Keep the file name, a later file with the same name is handled by the
+    /// [`ConflictPolicy`](crate::ConflictPolicy) like an existing one, so
+    /// by default it overwrites the earlier one. The default.
+    #[default]
+    FileName,
+    /// Stop with [`Error::DestinationExists`](crate::Error::DestinationExists)
+    /// when two files get the same name.
+    Error,
+    /// Add a number to the names of later files: `file-1 (2).txt`.
+    Numbered,
+    /// Encode the path relative to the extracted directory into the name:
+    /// `foo/bar/file-1.txt` becomes `foo__bar__file-1.txt`. Paths that
+    /// encode to the same name, like `foo__bar/file-1.txt`, stop with
+    /// [`Error::DestinationExists`](crate::Error::DestinationExists).
+    PathEncoded,
+    /// Extract files with the same content only once, files with the same
+    /// name and different content are numbered like with
+    /// [`Naming::Numbered`].
+    Dedupe,
+}
+
+/// Names the files of one [`extract_with`](crate::extract_with) call.
+pub(crate) struct Namer {
+    naming: Naming,
+    // Destinations of the files extracted so far.
+    claimed: HashSet<PathBuf>,
+    // Sources and destinations of the files extracted so far by size and
+    // content hash. Moved files are compared at their destination.
+    hashes: HashMap<(u64, u64), Vec<(PathBuf, PathBuf)>>,
+}
+
+impl Namer {
+    pub fn new(naming: Naming) -> Namer {
+        Namer {
+            naming,
+            claimed: HashSet::new(),
+            hashes: HashMap::new(),
+        }
+    }
+
+    /// Returns the destination in `to` of the `entry` from the extracted
+    /// directory `root`, or `None` if the same content was already
+    /// extracted. Existing files are looked up through `exec`.
+    pub fn name(
+        &mut self,
+        exec: &dyn Exec,
+        op: &'static str,
+        root: &Path,
+        entry: &Entry,
+        to: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let key = match self.naming {
+            Naming::Dedupe if entry.is_file() => {
+                let key = (
+                    entry.metadata().len(),
+                    content_hash(entry.path()).at(op, entry.path())?,
+                );
+                if self.is_duplicate(exec, key, entry).at(op, entry.path())? {
+                    return Ok(None);
+                }
+                Some(key)
+            }
+            _ => None,
+        };
+
+        let name = to.join(entry.path().file_name().unwrap());
+        let name = match self.naming {
+            Naming::FileName => name,
+            Naming::PathEncoded => {
+                let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                let mut encoded = OsString::new();
+                for (i, part) in rel.iter().enumerate() {
+                    if i > 0 {
+                        encoded.push(SEPARATOR);
+                    }
+                    encoded.push(part);
+                }
+                let name = to.join(encoded);
+                if self.claimed.contains(&name) {
+                    err!(DestinationExists, op, entry.path(), &name)
+                }
+                name
+            }
+            Naming::Error if self.claimed.contains(&name) => {
+                err!(DestinationExists, op, entry.path(), &name)
+            }
+            Naming::Error => name,
+            Naming::Numbered | Naming::Dedupe => {
+                if self.claimed.contains(&name) {
+                    let claimed = &self.claimed;
+                    utils::free_name_by(&name, |p| claimed.contains(p) || exec.exists(p))
+                } else {
+                    name
+                }
+            }
+        };
+
+        if let Some(key) = key {
+            let extracted = (entry.path().to_path_buf(), name.clone());
+            self.hashes.entry(key).or_default().push(extracted);
+        }
+        self.claimed.insert(name.clone());
+        Ok(Some(name))
+    }
+
+    /// Returns `true` if a file with the same content as `entry` was already
+    /// extracted. `key` is the size and the content hash of `entry`.
+    fn is_duplicate(&self, exec: &dyn Exec, key: (u64, u64), entry: &Entry) -> io::Result<bool> {
+        for (from, to) in self.hashes.get(&key).into_iter().flatten() {
+            let (path, meta) = exec
+                .lookup(from)
+                .or_else(|| exec.lookup(to))
+                .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+            if !is_different(entry.path(), entry.metadata(), &path, &meta)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Hashes the content of the file.
+fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 8192];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..read]);
+    }
+}
@@ -0,0 +1,645 @@
+use crate::{
+    batch::ErrorMode, cancel::CancelToken, conflict::ConflictPolicy, engine::Reflink,
+    filter::Filter, guard::Guard, naming::Naming, preserve::Preserve, progress::Progress,
+    prune::SortBy, symlink::SymlinkPolicy, usage::SizeMode, walk::Entry,
+};
+use std::time::{Duration, SystemTime};
+
+/// Options for [`ls_with`](crate::ls_with).
+#[derive(Debug, Clone, Default)]
+pub struct LsOptions {
+    pub(crate) filter: Filter,
+}
+
+impl LsOptions {
+    /// Creates options with the default values.
+    pub fn new() -> LsOptions {
+        LsOptions::default()
+    }
+
+    /// Sets the filter for the directory content.
+    pub fn filter(mut self, filter: Filter) -> LsOptions {
+        self.filter = filter;
+        self
+    }
+}
+
+/// Options for [`touch_with`](crate::touch_with).
+#[derive(Debug, Clone)]
+pub struct TouchOptions {
+    pub(crate) truncate: bool,
+    pub(crate) parents: bool,
+    pub(crate) on_error: ErrorMode,
+}
+
+impl TouchOptions {
+    /// Creates options with the default values.
+    pub fn new() -> TouchOptions {
+        TouchOptions::default()
+    }
+
+    /// Truncates files that already exist. Enabled by default, if disabled
+    /// the content is kept and only the modification time is updated, like
+    /// the `touch` command.
+    pub fn truncate(mut self, yes: bool) -> TouchOptions {
+        self.truncate = yes;
+        self
+    }
+
+    /// Creates the missing parent directories. Disabled by default.
+    pub fn parents(mut self, yes: bool) -> TouchOptions {
+        self.parents = yes;
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default.
+    pub fn on_error(mut self, mode: ErrorMode) -> TouchOptions {
+        self.on_error = mode;
+        self
+    }
+}
+
+impl Default for TouchOptions {
+    fn default() -> TouchOptions {
+        TouchOptions {
+            truncate: true,
+            parents: false,
+            on_error: ErrorMode::default(),
+        }
+    }
+}
+
+/// Options for [`mkdir_with`](crate::mkdir_with).
+#[derive(Debug, Clone)]
+pub struct MkdirOptions {
+    pub(crate) parents: bool,
+    pub(crate) exist_ok: bool,
+    pub(crate) mode: Option<u32>,
+    pub(crate) on_error: ErrorMode,
+}
+
+impl MkdirOptions {
+    /// Creates options with the default values.
+    pub fn new() -> MkdirOptions {
+        MkdirOptions::default()
+    }
+
+    /// Creates the missing parent directories. Enabled by default.
+    pub fn parents(mut self, yes: bool) -> MkdirOptions {
+        self.parents = yes;
+        self
+    }
+
+    /// Accepts directories that already exist instead of failing with
+    /// [`Error::DestinationExists`](crate::Error::DestinationExists), like
+    /// `mkdir -p`. Disabled by default.
+    pub fn exist_ok(mut self, yes: bool) -> MkdirOptions {
+        self.exist_ok = yes;
+        self
+    }
+
+    /// Sets the permissions of the created directory, e.g. `0o700`, before
+    /// the umask is applied. Parent directories get the default ones.
+    /// Ignored on platforms other than Unix.
+    pub fn mode(mut self, mode: u32) -> MkdirOptions {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default.
+    pub fn on_error(mut self, mode: ErrorMode) -> MkdirOptions {
+        self.on_error = mode;
+        self
+    }
+}
+
+impl Default for MkdirOptions {
+    fn default() -> MkdirOptions {
+        MkdirOptions {
+            parents: true,
+            exist_ok: false,
+            mode: None,
+            on_error: ErrorMode::default(),
+        }
+    }
+}
+
+/// Options for [`cp_with`](crate::cp_with) and [`mv_with`](crate::mv_with).
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions {
+    pub(crate) filter: Filter,
+    pub(crate) conflict: ConflictPolicy,
+    pub(crate) preserve: Preserve,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) rollback: bool,
+    pub(crate) progress: Progress,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) guard: Guard,
+    pub(crate) threads: Option<usize>,
+    pub(crate) on_error: ErrorMode,
+    pub(crate) no_target_directory: bool,
+    pub(crate) create_target: bool,
+    pub(crate) reflink: Reflink,
+}
+
+impl CopyOptions {
+    /// Creates options with the default values.
+    pub fn new() -> CopyOptions {
+        CopyOptions::default()
+    }
+
+    /// Sets the filter for the copied entries. Excluded directories are
+    /// skipped with their whole content.
+    pub fn filter(mut self, filter: Filter) -> CopyOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets what to do with entries whose destination already exists,
+    /// [`ConflictPolicy::Overwrite`] by default.
+    pub fn conflict(mut self, policy: ConflictPolicy) -> CopyOptions {
+        self.conflict = policy;
+        self
+    }
+
+    /// Sets the metadata carried over to the copies of files and
+    /// directories, [`Preserve::none`] by default. Ignored by
+    /// [`mv_with`](crate::mv_with), which always preserves everything.
+    pub fn preserve(mut self, preserve: Preserve) -> CopyOptions {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Sets what to do with symlinks, [`SymlinkPolicy::CopyLink`] by default.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> CopyOptions {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Makes [`mv_with`](crate::mv_with) all or nothing: if moving fails,
+    /// everything moved by the call is moved back. Overwritten destinations
+    /// are moved aside until the move is complete, so they are put back
+    /// too. Disabled by default, ignored by [`cp_with`](crate::cp_with).
+    pub fn rollback(mut self, yes: bool) -> CopyOptions {
+        self.rollback = yes;
+        self
+    }
+
+    /// Sets the callback for progress events. A directory moved by renaming
+    /// is reported as one entry, and its whole size is reported with one
+    /// [`Event::BytesCopied`](crate::Event::BytesCopied) if the prescan is
+    /// enabled.
+    pub fn progress(mut self, progress: Progress) -> CopyOptions {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the token to cancel the operation with.
+    pub fn cancel(mut self, token: CancelToken) -> CopyOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Sets the guard for the moved paths, [`Guard::new`] by default.
+    /// Ignored by [`cp_with`](crate::cp_with), which leaves the sources
+    /// in place.
+    pub fn guard(mut self, guard: Guard) -> CopyOptions {
+        self.guard = guard;
+        self
+    }
+
+    /// Copies independent subtrees concurrently on `threads` threads, `0`
+    /// for the number of CPUs. A directory is still created before its
+    /// content, and its metadata is preserved after it. Sequential by
+    /// default.
+    pub fn threads(mut self, threads: usize) -> CopyOptions {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default. Ignored by [`mv_with`](crate::mv_with), which reports
+    /// every source in its [`MoveReport`](crate::MoveReport).
+    pub fn on_error(mut self, mode: ErrorMode) -> CopyOptions {
+        self.on_error = mode;
+        self
+    }
+
+    /// Treats `to` as the new path of the single source instead of the
+    /// directory to put it into, like `cp -T`: `a.txt` is copied to `b.txt`
+    /// and `dir` to `backup`. An existing `to` is handled by the
+    /// [`ConflictPolicy`]. Disabled by default.
+    pub fn no_target_directory(mut self, yes: bool) -> CopyOptions {
+        self.no_target_directory = yes;
+        self
+    }
+
+    /// Creates the missing target directory with its missing parents: `to`
+    /// itself, or its parent with
+    /// [`no_target_directory`](CopyOptions::no_target_directory). Disabled
+    /// by default, a missing target directory fails with
+    /// [`Error::NotFound`](crate::Error::NotFound) before anything is copied.
+    pub fn create_target(mut self, yes: bool) -> CopyOptions {
+        self.create_target = yes;
+        self
+    }
+
+    /// Sets whether files are cloned as reflinks, [`Reflink::Auto`] by
+    /// default. Files moved by renaming are not copied at all.
+    pub fn reflink(mut self, reflink: Reflink) -> CopyOptions {
+        self.reflink = reflink;
+        self
+    }
+}
+
+/// Options for [`rm_with`](crate::rm_with).
+#[derive(Debug, Clone, Default)]
+pub struct RemoveOptions {
+    pub(crate) filter: Filter,
+    pub(crate) progress: Progress,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) guard: Guard,
+    pub(crate) threads: Option<usize>,
+    pub(crate) on_error: ErrorMode,
+}
+
+impl RemoveOptions {
+    /// Creates options with the default values.
+    pub fn new() -> RemoveOptions {
+        RemoveOptions::default()
+    }
+
+    /// Sets the filter for the deleted entries. Excluded directories are kept
+    /// with their whole content. Other directories are deleted only if they
+    /// match the include patterns or their content was deleted, and only if
+    /// they are empty afterwards.
+    pub fn filter(mut self, filter: Filter) -> RemoveOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the callback for progress events. Deleting reports entries
+    /// only, no bytes.
+    pub fn progress(mut self, progress: Progress) -> RemoveOptions {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the token to cancel the operation with.
+    pub fn cancel(mut self, token: CancelToken) -> RemoveOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Sets the guard for the deleted paths, [`Guard::new`] by default.
+    pub fn guard(mut self, guard: Guard) -> RemoveOptions {
+        self.guard = guard;
+        self
+    }
+
+    /// Deletes independent subtrees concurrently on `threads` threads, `0`
+    /// for the number of CPUs. A directory is still deleted after its
+    /// content. Sequential by default.
+    pub fn threads(mut self, threads: usize) -> RemoveOptions {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default.
+    pub fn on_error(mut self, mode: ErrorMode) -> RemoveOptions {
+        self.on_error = mode;
+        self
+    }
+}
+
+/// Options for [`clean_with`](crate::clean_with).
+#[derive(Debug, Clone, Default)]
+pub struct CleanOptions {
+    pub(crate) keep: Filter,
+    pub(crate) older_than: Option<Duration>,
+    pub(crate) guard: Guard,
+    pub(crate) on_error: ErrorMode,
+}
+
+impl CleanOptions {
+    /// Creates options with the default values.
+    pub fn new() -> CleanOptions {
+        CleanOptions::default()
+    }
+
+    /// Adds a pattern for entries to keep, e.g. `.gitkeep`. Patterns are
+    /// matched like in [`Filter`], relative to the cleaned directory. A kept
+    /// directory is kept with its whole content, and so are the directories
+    /// containing kept entries.
+    pub fn keep(mut self, pattern: &str) -> CleanOptions {
+        self.keep = self.keep.exclude(pattern);
+        self
+    }
+
+    /// Deletes only the entries modified more than `age` ago. Newer files
+    /// are kept together with the directories containing them.
+    pub fn older_than(mut self, age: Duration) -> CleanOptions {
+        self.older_than = Some(age);
+        self
+    }
+
+    /// Sets the guard for the cleaned directories, [`Guard::new`] by
+    /// default.
+    pub fn guard(mut self, guard: Guard) -> CleanOptions {
+        self.guard = guard;
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default.
+    pub fn on_error(mut self, mode: ErrorMode) -> CleanOptions {
+        self.on_error = mode;
+        self
+    }
+
+    /// Returns `true` if some entries may be kept.
+    pub(crate) fn is_partial(&self) -> bool {
+        !self.keep.is_empty() || self.older_than.is_some()
+    }
+
+    /// Returns `true` if the entry is old enough to be deleted. Entries
+    /// without a modification time are kept.
+    pub(crate) fn is_old(&self, entry: &Entry) -> bool {
+        let age = match self.older_than {
+            Some(age) => age,
+            None => return true,
+        };
+        let modified = match entry.metadata().modified() {
+            Ok(modified) => modified,
+            Err(_) => return false,
+        };
+        SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|elapsed| elapsed > age)
+    }
+}
+
+/// Options for [`extract_with`](crate::extract_with).
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub(crate) filter: Filter,
+    pub(crate) naming: Naming,
+    pub(crate) conflict: ConflictPolicy,
+    pub(crate) preserve: Preserve,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) move_files: bool,
+    pub(crate) remove_root: bool,
+    pub(crate) progress: Progress,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) threads: Option<usize>,
+    pub(crate) on_error: ErrorMode,
+    pub(crate) reflink: Reflink,
+}
+
+impl ExtractOptions {
+    /// Creates options with the default values.
+    pub fn new() -> ExtractOptions {
+        ExtractOptions::default()
+    }
+
+    /// Sets the filter for the extracted files. Files inside excluded
+    /// directories are not extracted.
+    pub fn filter(mut self, filter: Filter) -> ExtractOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets how the extracted files are named, [`Naming::FileName`] by
+    /// default.
+    pub fn naming(mut self, naming: Naming) -> ExtractOptions {
+        self.naming = naming;
+        self
+    }
+
+    /// Sets what to do with files whose destination already exists,
+    /// [`ConflictPolicy::Overwrite`] by default.
+    pub fn conflict(mut self, policy: ConflictPolicy) -> ExtractOptions {
+        self.conflict = policy;
+        self
+    }
+
+    /// Sets the metadata carried over to the extracted files,
+    /// [`Preserve::none`] by default. Ignored when the files are moved,
+    /// which always preserves everything.
+    pub fn preserve(mut self, preserve: Preserve) -> ExtractOptions {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Sets what to do with symlinks, [`SymlinkPolicy::CopyLink`] by default.
+    /// Symlinks to directories are extracted as symlinks unless they are
+    /// followed.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> ExtractOptions {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Moves the files instead of copying them, renaming them if possible.
+    /// Directories emptied by moving their files are deleted afterwards.
+    /// Files skipped because of a conflict are left in place, and so are
+    /// their directories. With [`Naming::Dedupe`] the duplicates are
+    /// deleted. Disabled by default.
+    pub fn move_files(mut self, yes: bool) -> ExtractOptions {
+        self.move_files = yes;
+        self
+    }
+
+    /// Deletes the extracted directory itself too if it was emptied by
+    /// [`move_files`](ExtractOptions::move_files). A symlink passed as the
+    /// directory is kept. Disabled by default.
+    pub fn remove_root(mut self, yes: bool) -> ExtractOptions {
+        self.remove_root = yes;
+        self
+    }
+
+    /// Sets the callback for progress events. Only files are reported.
+    pub fn progress(mut self, progress: Progress) -> ExtractOptions {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the token to cancel the operation with.
+    pub fn cancel(mut self, token: CancelToken) -> ExtractOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Walks and extracts independent subtrees concurrently on `threads`
+    /// threads, `0` for the number of CPUs. Which of the files with the same
+    /// name gets the plain name then depends on timing. Sequential by
+    /// default.
+    pub fn threads(mut self, threads: usize) -> ExtractOptions {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Sets what to do when one of the paths fails, [`ErrorMode::Strict`]
+    /// by default.
+    pub fn on_error(mut self, mode: ErrorMode) -> ExtractOptions {
+        self.on_error = mode;
+        self
+    }
+
+    /// Sets whether the extracted files are cloned as reflinks,
+    /// [`Reflink::Auto`] by default. Moved files are cloned only if they
+    /// can't be renamed.
+    pub fn reflink(mut self, reflink: Reflink) -> ExtractOptions {
+        self.reflink = reflink;
+        self
+    }
+}
+
+/// Options for [`get_size_with`](crate::get_size_with) and
+/// [`get_usage_with`](crate::get_usage_with).
+#[derive(Debug, Clone, Default)]
+pub struct SizeOptions {
+    pub(crate) filter: Filter,
+    pub(crate) mode: SizeMode,
+    pub(crate) dedupe_hard_links: bool,
+    pub(crate) progress: Progress,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) threads: Option<usize>,
+}
+
+impl SizeOptions {
+    /// Creates options with the default values.
+    pub fn new() -> SizeOptions {
+        SizeOptions::default()
+    }
+
+    /// Sets the filter for the counted entries.
+    pub fn filter(mut self, filter: Filter) -> SizeOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets how the entries are measured, [`SizeMode::Length`] by default.
+    pub fn mode(mut self, mode: SizeMode) -> SizeOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// Counts a file with several hard links in the tree only once, like
+    /// `du`. Disabled by default.
+    pub fn dedupe_hard_links(mut self, yes: bool) -> SizeOptions {
+        self.dedupe_hard_links = yes;
+        self
+    }
+
+    /// Sets the callback for progress events. Every counted entry is
+    /// reported with [`Event::EntryFinished`](crate::Event::EntryFinished).
+    pub fn progress(mut self, progress: Progress) -> SizeOptions {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the token to cancel the operation with.
+    pub fn cancel(mut self, token: CancelToken) -> SizeOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Walks independent subtrees concurrently on `threads` threads, `0` for
+    /// the number of CPUs. Ignored by [`du_with`](crate::du_with).
+    /// Sequential by default.
+    pub fn threads(mut self, threads: usize) -> SizeOptions {
+        self.threads = Some(threads);
+        self
+    }
+}
+
+/// Options for [`prune`](crate::prune). Without limits nothing is deleted.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    pub(crate) max_count: Option<usize>,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) sort: SortBy,
+    pub(crate) filter: Filter,
+    pub(crate) guard: Guard,
+}
+
+impl PruneOptions {
+    /// Creates options with the default values.
+    pub fn new() -> PruneOptions {
+        PruneOptions::default()
+    }
+
+    /// Keeps at most `count` entries.
+    pub fn max_count(mut self, count: usize) -> PruneOptions {
+        self.max_count = Some(count);
+        self
+    }
+
+    /// Keeps the entries while their total size is at most `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> PruneOptions {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Deletes the entries modified more than `age` ago.
+    pub fn max_age(mut self, age: Duration) -> PruneOptions {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Sets the order of the entries, [`SortBy::Modified`] by default.
+    pub fn sort(mut self, sort: SortBy) -> PruneOptions {
+        self.sort = sort;
+        self
+    }
+
+    /// Sets the filter for the entries of the directory. Excluded entries
+    /// are neither deleted nor counted.
+    pub fn filter(mut self, filter: Filter) -> PruneOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the guard for the directory and the deleted entries,
+    /// [`Guard::new`] by default.
+    pub fn guard(mut self, guard: Guard) -> PruneOptions {
+        self.guard = guard;
+        self
+    }
+}
+
+/// Options for [`trash_with`](crate::trash_with),
+/// [`list_trash_with`](crate::list_trash_with) and
+/// [`empty_trash_with`](crate::empty_trash_with).
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default)]
+pub struct TrashOptions {
+    pub(crate) guard: Guard,
+    pub(crate) home_only: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl TrashOptions {
+    /// Creates options with the default values.
+    pub fn new() -> TrashOptions {
+        TrashOptions::default()
+    }
+
+    /// Sets the guard for the trashed paths, [`Guard::new`] by default.
+    pub fn guard(mut self, guard: Guard) -> TrashOptions {
+        self.guard = guard;
+        self
+    }
+
+    /// Lists and empties only the home trash, leaving out the trashes of the
+    /// mounted filesystems. Items are still trashed to the trash of their
+    /// filesystem. Disabled by default.
+    pub fn home_only(mut self, yes: bool) -> TrashOptions {
+        self.home_only = yes;
+        self
+    }
+}
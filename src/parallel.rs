@@ -0,0 +1,229 @@
+//! Parallel execution of tree operations on std threads.
+//!
+//! Every directory is a task. A worker walks the direct content of the
+//! directory, processes the files and queues the subdirectories as new
+//! tasks, so independent subtrees are processed concurrently. A task is
+//! finished after all the tasks queued by it, which gives the post-order
+//! needed to delete directories or to preserve their metadata.
+use crate::{
+    error::{Error, Result},
+    walk::{file_id, Entry, FileId, Walk},
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+/// A directory to process, with the data of the operation and the ids of
+/// the directories above it.
+pub(crate) struct Subtree<T> {
+    pub entry: Entry,
+    pub data: T,
+    ancestors: Vec<FileId>,
+}
+
+impl<T> Subtree<T> {
+    pub fn root(entry: Entry, data: T) -> Subtree<T> {
+        Subtree {
+            entry,
+            data,
+            ancestors: vec![],
+        }
+    }
+
+    /// Returns the walk over the direct content of the directory. `root` is
+    /// the root of the operation, filters are matched relative to it.
+    pub fn walk(&self, root: &Path) -> Walk {
+        Walk::new(self.entry.path())
+            .relative_to(root)
+            .children_of(self.entry.depth(), self.ancestors.clone())
+    }
+
+    /// Returns a subdirectory found by [`walk`](Subtree::walk).
+    pub fn child(&self, entry: Entry, data: T) -> Subtree<T> {
+        let mut ancestors = self.ancestors.clone();
+        ancestors.extend(file_id(self.entry.metadata()));
+        Subtree {
+            entry,
+            data,
+            ancestors,
+        }
+    }
+}
+
+/// Returns the number of threads for the `threads` option: `1` if it's not
+/// set and the number of available CPUs for `0`.
+pub(crate) fn threads(threads: Option<usize>) -> usize {
+    match threads {
+        None => 1,
+        Some(0) => thread::available_parallelism().map_or(1, |n| n.get()),
+        Some(n) => n,
+    }
+}
+
+/// A visited task waiting for the tasks it has queued.
+struct Node<T> {
+    task: Mutex<Option<T>>,
+    // The queued tasks that aren't finished, plus one for the task itself.
+    left: AtomicUsize,
+    parent: Option<Arc<Node<T>>>,
+}
+
+struct Queue<T> {
+    tasks: Vec<(T, Option<Arc<Node<T>>>)>,
+    // Number of tasks being visited.
+    active: usize,
+    failed: Option<Error>,
+}
+
+/// Processes the tree from the `root` task on `threads` threads. `visit`
+/// processes a task and pushes the new tasks to the vector, `finish` is
+/// called after all the tasks pushed by the task are finished.
+///
+/// The first error stops the workers, the tasks being processed are
+/// completed and the error is returned.
+pub(crate) fn run<T, V, F>(threads: usize, root: T, visit: V, finish: F) -> Result<()>
+where
+    T: Send,
+    V: Fn(&T, &mut Vec<T>) -> Result<()> + Sync,
+    F: Fn(T) -> Result<()> + Sync,
+{
+    let queue = Mutex::new(Queue {
+        tasks: vec![(root, None)],
+        active: 0,
+        failed: None,
+    });
+    let changed = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| work(&queue, &changed, &visit, &finish));
+        }
+    });
+
+    match queue.into_inner().unwrap().failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn work<T, V, F>(queue: &Mutex<Queue<T>>, changed: &Condvar, visit: &V, finish: &F)
+where
+    V: Fn(&T, &mut Vec<T>) -> Result<()>,
+    F: Fn(T) -> Result<()>,
+{
+    loop {
+        let (task, parent) = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if queue.failed.is_some() {
+                    return;
+                }
+                if let Some(task) = queue.tasks.pop() {
+                    queue.active += 1;
+                    break task;
+                }
+                if queue.active == 0 {
+                    return;
+                }
+                queue = changed.wait(queue).unwrap();
+            }
+        };
+
+        let mut children = vec![];
+        let res = visit(&task, &mut children);
+
+        let mut locked = queue.lock().unwrap();
+        locked.active -= 1;
+        let node = match res {
+            Ok(()) => {
+                let node = Arc::new(Node {
+                    task: Mutex::new(Some(task)),
+                    left: AtomicUsize::new(children.len() + 1),
+                    parent,
+                });
+                let queued = children
+                    .into_iter()
+                    .map(|child| (child, Some(node.clone())));
+                locked.tasks.extend(queued);
+                Some(node)
+            }
+            Err(e) => {
+                locked.failed.get_or_insert(e);
+                None
+            }
+        };
+        drop(locked);
+        changed.notify_all();
+
+        if let Some(node) = node {
+            if let Err(e) = complete(node, finish) {
+                queue.lock().unwrap().failed.get_or_insert(e);
+                changed.notify_all();
+            }
+        }
+    }
+}
+
+/// Counts one more finished part of the node, and finishes the node and its
+/// parents that have nothing left.
+fn complete<T, F>(mut node: Arc<Node<T>>, finish: &F) -> Result<()>
+where
+    F: Fn(T) -> Result<()>,
+{
+    loop {
+        if node.left.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return Ok(());
+        }
+        if let Some(task) = node.task.lock().unwrap().take() {
+            finish(task)?;
+        }
+        match node.parent.clone() {
+            Some(parent) => node = parent,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Paths being written by the threads. A thread claims the destination
+/// before checking whether it exists, so a conflict between two threads is
+/// resolved like a conflict with an existing file.
+#[derive(Default)]
+pub(crate) struct Claims {
+    paths: Mutex<HashSet<PathBuf>>,
+    released: Condvar,
+}
+
+impl Claims {
+    /// Waits until no other thread has claimed `path` and claims it until
+    /// the returned value is dropped.
+    pub fn claim(&self, path: &Path) -> Claim<'_> {
+        let mut paths = self.paths.lock().unwrap();
+        while paths.contains(path) {
+            paths = self.released.wait(paths).unwrap();
+        }
+        paths.insert(path.to_path_buf());
+
+        Claim {
+            claims: self,
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+pub(crate) struct Claim<'a> {
+    claims: &'a Claims,
+    path: PathBuf,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.claims.paths.lock().unwrap().remove(&self.path);
+        self.claims.released.notify_all();
+    }
+}
@@ -0,0 +1,891 @@
+//! Dry runs: operations planned as a list of actions, executed later.
+//!
+//! A plan runs the operation itself with a [`Planner`] in place of the disk,
+//! so the planned actions come from the same decisions as the real run.
+#[cfg(target_os = "linux")]
+use crate::options::TrashOptions;
+use crate::{
+    conflict::ConflictAction,
+    engine::{self, Reflink},
+    error::{Result, ResultExt},
+    exec::{Disk, Exec},
+    options::{
+        CleanOptions, CopyOptions, ExtractOptions, MkdirOptions, PruneOptions, RemoveOptions,
+        TouchOptions,
+    },
+    preserve::Preserve,
+    progress::Monitor,
+    prune,
+    symlink::{copy_link, SymlinkPolicy},
+    transfer, utils, walk, Entry,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{
+        create_dir, metadata, read_dir, remove_dir, remove_file, rename, symlink_metadata, Metadata,
+    },
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A single change to the filesystem planned by a [`Plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Create a directory, with the permissions of `mode` on Unix if it's
+    /// set.
+    CreateDir { path: PathBuf, mode: Option<u32> },
+    /// Create an empty file. An existing file is truncated, or only gets
+    /// a new modification time if `truncate` isn't set.
+    CreateFile { path: PathBuf, truncate: bool },
+    /// Copy a file or a symlink, or create a directory for the copied
+    /// content. The size of a directory is 0.
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        size: u64,
+    },
+    /// Rename a file, a symlink or a directory with its content. The size is
+    /// the size of the whole tree.
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        size: u64,
+    },
+    /// Delete a file, a symlink or an empty directory.
+    Delete { path: PathBuf, size: u64 },
+    /// Move a file, a symlink or a directory with its content to the trash.
+    /// The size is the size of the whole tree.
+    #[cfg(target_os = "linux")]
+    Trash { path: PathBuf, size: u64 },
+}
+
+impl Action {
+    /// Returns the number of bytes copied, moved or deleted by the action.
+    pub fn size(&self) -> u64 {
+        match self {
+            Action::CreateDir { .. } | Action::CreateFile { .. } => 0,
+            Action::Copy { size, .. }
+            | Action::Rename { size, .. }
+            | Action::Delete { size, .. } => *size,
+            #[cfg(target_os = "linux")]
+            Action::Trash { size, .. } => *size,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CreateDir {
+                path,
+                mode: Some(mode),
+            } => write!(f, "create dir {:?} (mode {:o})", path, mode),
+            Action::CreateDir { path, mode: None } => write!(f, "create dir {:?}", path),
+            Action::CreateFile {
+                path,
+                truncate: true,
+            } => write!(f, "create file {:?}", path),
+            Action::CreateFile {
+                path,
+                truncate: false,
+            } => write!(f, "touch file {:?}", path),
+            Action::Copy { from, to, size } => {
+                write!(f, "copy {:?} -> {:?} ({} B)", from, to, size)
+            }
+            Action::Rename { from, to, size } => {
+                write!(f, "rename {:?} -> {:?} ({} B)", from, to, size)
+            }
+            Action::Delete { path, size } => write!(f, "delete {:?} ({} B)", path, size),
+            #[cfg(target_os = "linux")]
+            Action::Trash { path, size } => write!(f, "trash {:?} ({} B)", path, size),
+        }
+    }
+}
+
+/// An entry whose destination already exists, with the decision of the
+/// [`ConflictPolicy`](crate::ConflictPolicy).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    from: PathBuf,
+    to: PathBuf,
+    action: ConflictAction,
+}
+
+impl Conflict {
+    /// Returns the path of the entry.
+    pub fn from_path(&self) -> &Path {
+        &self.from
+    }
+
+    /// Returns the existing destination.
+    pub fn to_path(&self) -> &Path {
+        &self.to
+    }
+
+    /// Returns what is done with the entry.
+    pub fn action(&self) -> ConflictAction {
+        self.action
+    }
+}
+
+/// The list of actions an operation would perform, built without changing
+/// anything on disk.
+///
+/// A plan is built with the function named after the operation, e.g.
+/// [`Plan::rm`] or [`Plan::cp_with`], takes the same arguments and fails
+/// with the same errors as the operation itself: the operation runs with
+/// every change recorded instead of made. The plan can be printed or
+/// inspected, and then [`executed`](Plan::execute): the execution performs
+/// exactly the planned actions, so the dry run and the real run can't
+/// diverge. Plans are built on one thread, progress callbacks and cancel
+/// tokens from the options are not used by plans.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::Plan;
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///
+///  let plan = Plan::rm(&vec![&dir])?;
+///  println!("{}", plan);
+///  plan.execute()?;
+/// ```
+#[derive(Debug)]
+pub struct Plan {
+    op: &'static str,
+    actions: Vec<Action>,
+    conflicts: Vec<Conflict>,
+    preserve: Preserve,
+    symlinks: SymlinkPolicy,
+    reflink: Reflink,
+}
+
+impl Plan {
+    /// Plans [`rm`](crate::rm).
+    pub fn rm(from: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Plan> {
+        Plan::rm_with(from, &RemoveOptions::default())
+    }
+
+    /// Plans [`rm_with`](crate::rm_with).
+    pub fn rm_with(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &RemoveOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("rm");
+        crate::rm_paths(&planner, &utils::paths(from), options, &Monitor::none())?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Plans [`clean`](crate::clean): deletes the content of the
+    /// directories.
+    pub fn clean(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Plan> {
+        Plan::clean_with(paths, &CleanOptions::default())
+    }
+
+    /// Plans [`clean_with`](crate::clean_with).
+    pub fn clean_with(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &CleanOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("clean");
+        crate::clean_paths(&planner, &utils::paths(paths), options)?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Plans [`touch`](crate::touch). Existing files are reported as
+    /// conflicts, as they are truncated.
+    pub fn touch(file_paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Plan> {
+        Plan::touch_with(file_paths, &TouchOptions::default())
+    }
+
+    /// Plans [`touch_with`](crate::touch_with). Existing files are reported
+    /// as conflicts if they are truncated.
+    pub fn touch_with(
+        file_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &TouchOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("touch");
+        let create_type = utils::CreateType::File(options);
+        utils::create(&planner, &utils::paths(file_paths), &create_type)?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Plans [`mkdir`](crate::mkdir), including the missing parent
+    /// directories.
+    pub fn mkdir(dir_paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Plan> {
+        Plan::mkdir_with(dir_paths, &MkdirOptions::default())
+    }
+
+    /// Plans [`mkdir_with`](crate::mkdir_with).
+    pub fn mkdir_with(
+        dir_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &MkdirOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("mkdir");
+        let create_type = utils::CreateType::Dir(options);
+        utils::create(&planner, &utils::paths(dir_paths), &create_type)?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Plans [`cp`](crate::cp).
+    pub fn cp(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+    ) -> Result<Plan> {
+        Plan::cp_with(from, to, &CopyOptions::default())
+    }
+
+    /// Plans [`cp_with`](crate::cp_with).
+    pub fn cp_with(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+        options: &CopyOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("cp");
+        let from = utils::paths(from);
+        crate::cp_paths(&planner, &from, to.as_ref(), options, &Monitor::none())?;
+        Ok(planner.finish(options.preserve, options.symlinks, options.reflink))
+    }
+
+    /// Plans [`mv`](crate::mv).
+    pub fn mv(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+    ) -> Result<Plan> {
+        Plan::mv_with(from, to, &CopyOptions::default())
+    }
+
+    /// Plans [`mv_with`](crate::mv_with). Entries on the same filesystem as
+    /// the destination are renamed, others are copied and deleted.
+    pub fn mv_with(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+        options: &CopyOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("mv");
+        let from = utils::paths(from);
+        crate::mv_paths(&planner, &from, to.as_ref(), options, &Monitor::none())?;
+        Ok(planner.finish(Preserve::all(), options.symlinks, options.reflink))
+    }
+
+    /// Plans [`extract`](crate::extract).
+    pub fn extract(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+    ) -> Result<Plan> {
+        Plan::extract_with(from, to, &ExtractOptions::default())
+    }
+
+    /// Plans [`extract_with`](crate::extract_with).
+    pub fn extract_with(
+        from: impl IntoIterator<Item = impl AsRef<Path>>,
+        to: impl AsRef<Path>,
+        options: &ExtractOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("extract");
+        let from = utils::paths(from);
+        crate::extract_paths(&planner, &from, to.as_ref(), options, &Monitor::none())?;
+
+        let preserve = match options.move_files {
+            true => Preserve::all(),
+            false => options.preserve,
+        };
+        Ok(planner.finish(preserve, options.symlinks, options.reflink))
+    }
+
+    /// Plans [`prune`](crate::prune).
+    pub fn prune(dir: impl AsRef<Path>, options: &PruneOptions) -> Result<Plan> {
+        let planner = Planner::new("prune");
+        prune::prune_dir(&planner, dir.as_ref(), options)?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Plans [`trash`](crate::trash).
+    #[cfg(target_os = "linux")]
+    pub fn trash(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Plan> {
+        Plan::trash_with(paths, &TrashOptions::new())
+    }
+
+    /// Plans [`trash_with`](crate::trash_with).
+    #[cfg(target_os = "linux")]
+    pub fn trash_with(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &TrashOptions,
+    ) -> Result<Plan> {
+        let planner = Planner::new("trash");
+        crate::trash::trash_paths(&planner, &utils::paths(paths), options)?;
+        Ok(planner.finish(
+            Preserve::none(),
+            SymlinkPolicy::default(),
+            Reflink::default(),
+        ))
+    }
+
+    /// Returns the planned actions in the order they are executed.
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Returns the entries whose destination already exists.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Returns `true` if the plan has no actions.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Performs the planned actions in order, stopping on the first error.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filesystem has changed since the plan was
+    /// built so that an action can't be performed, e.g. a directory to
+    /// delete isn't empty anymore, or if an action fails.
+    pub fn execute(&self) -> Result<()> {
+        let op = self.op;
+        // Metadata of directories is preserved after their content is copied.
+        let mut dirs: Vec<(&Path, Metadata, &Path)> = vec![];
+
+        for action in &self.actions {
+            match action {
+                Action::CreateDir { path, mode } => Disk.create_dir(path, *mode).at(op, path)?,
+                Action::CreateFile { path, truncate } => {
+                    Disk.create_file(path, *truncate).at(op, path)?
+                }
+                Action::Copy { from, to, .. } => {
+                    let meta = match self.symlinks {
+                        SymlinkPolicy::Follow => metadata(from),
+                        _ => symlink_metadata(from),
+                    }
+                    .at(op, from)?;
+
+                    if meta.is_dir() {
+                        create_dir(to).between(op, from, to)?;
+                        dirs.push((from, meta, to));
+                        continue;
+                    } else if meta.file_type().is_symlink() {
+                        copy_link(from, to).between(op, from, to)?;
+                    } else {
+                        engine::copy(op, from, &meta, to, self.reflink, &Monitor::none())?;
+                    }
+                    self.preserve.apply(op, from, &meta, to)?;
+                }
+                Action::Rename { from, to, .. } => match rename(from, to) {
+                    Ok(()) => {}
+                    // The destination has moved to another filesystem.
+                    Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+                        let options = CopyOptions::new()
+                            .preserve(Preserve::all())
+                            .symlinks(self.symlinks)
+                            .reflink(self.reflink);
+                        transfer::copy_tree(&Disk, op, from, to, &options, &Monitor::none())?;
+                        Disk.remove_tree(op, from)?;
+                    }
+                    Err(e) => return Err(e).between(op, from, to),
+                },
+                Action::Delete { path, .. } => {
+                    if symlink_metadata(path).at(op, path)?.is_dir() {
+                        remove_dir(path).at(op, path)?;
+                    } else {
+                        remove_file(path).at(op, path)?;
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                Action::Trash { path, .. } => Disk.trash(path)?,
+            }
+        }
+
+        for (from, meta, to) in dirs.into_iter().rev() {
+            self.preserve.apply(op, from, &meta, to)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(
+                f,
+                "conflict {:?} -> {:?}: {:?}",
+                conflict.from, conflict.to, conflict.action
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the changes of an operation as actions instead of making them.
+///
+/// The planner keeps track of the paths created and deleted by the planned
+/// actions, so later changes see them as if they were made, and fails
+/// a change where the filesystem would fail it, e.g. deleting a directory
+/// that isn't emptied by the plan.
+struct Planner {
+    op: &'static str,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    actions: Vec<Action>,
+    conflicts: Vec<Conflict>,
+    created: HashMap<PathBuf, Planned>,
+    deleted: HashSet<PathBuf>,
+}
+
+/// A path created by the plan.
+enum Planned {
+    /// A copy of the source with its metadata. A copied directory gets its
+    /// content entry by entry.
+    Copy(PathBuf, Metadata),
+    /// A renamed file, symlink or directory with its content, which is
+    /// still at the source on disk.
+    Renamed(PathBuf),
+    Dir,
+    File,
+}
+
+impl Planner {
+    fn new(op: &'static str) -> Planner {
+        Planner {
+            op,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn finish(self, preserve: Preserve, symlinks: SymlinkPolicy, reflink: Reflink) -> Plan {
+        let state = self.state.into_inner().unwrap();
+        Plan {
+            op: self.op,
+            actions: state.actions,
+            conflicts: state.conflicts,
+            preserve,
+            symlinks,
+            reflink,
+        }
+    }
+}
+
+impl State {
+    fn push(&mut self, action: Action) {
+        match &action {
+            Action::CreateDir { path, .. } => self.create(path, Planned::Dir),
+            Action::CreateFile { path, .. } => {
+                if !self.exists(path) {
+                    self.create(path, Planned::File);
+                }
+            }
+            Action::Copy { from, to, .. } => {
+                if let Some((source, meta)) = self.lookup(from) {
+                    self.create(to, Planned::Copy(source, meta));
+                }
+            }
+            Action::Rename { from, to, .. } => {
+                if let Some((source, _)) = self.lookup(from) {
+                    // Entries planned inside the directory move with it.
+                    let inside: Vec<PathBuf> = self
+                        .created
+                        .keys()
+                        .filter(|p| p.starts_with(from) && *p != from)
+                        .cloned()
+                        .collect();
+                    for path in inside {
+                        let planned = self.created.remove(&path).unwrap();
+                        let path_to = to.join(path.strip_prefix(from).unwrap());
+                        self.created.insert(path_to, planned);
+                    }
+                    // A planned entry keeps what it is at the new path.
+                    let planned = self.created.remove(from.as_path());
+                    self.remove(from);
+                    self.create(to, planned.unwrap_or(Planned::Renamed(source)));
+                }
+            }
+            Action::Delete { path, .. } => self.remove(path),
+            #[cfg(target_os = "linux")]
+            Action::Trash { path, .. } => self.remove(path),
+        }
+        self.actions.push(action);
+    }
+
+    fn create(&mut self, path: &Path, planned: Planned) {
+        self.deleted.remove(path);
+        self.created.insert(path.to_path_buf(), planned);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.created.remove(path);
+        self.deleted.insert(path.to_path_buf());
+    }
+
+    fn is_deleted(&self, path: &Path) -> bool {
+        path.ancestors()
+            .take_while(|p| !self.created.contains_key(*p))
+            .any(|p| self.deleted.contains(p))
+    }
+
+    /// Returns the path holding the content of `path` in the planned state
+    /// and its metadata, see [`Exec::lookup`]. A directory created by the
+    /// plan has the metadata of its closest existing parent.
+    fn lookup(&self, path: &Path) -> Option<(PathBuf, Metadata)> {
+        if self.is_deleted(path) {
+            return None;
+        }
+
+        for ancestor in path.ancestors() {
+            let planned = match self.created.get(ancestor) {
+                Some(planned) => planned,
+                None => continue,
+            };
+            return match planned {
+                Planned::Renamed(source) => {
+                    // Joining an empty path would add a trailing slash.
+                    let source = match path.strip_prefix(ancestor).unwrap() {
+                        rel if rel.as_os_str().is_empty() => source.clone(),
+                        rel => source.join(rel),
+                    };
+                    symlink_metadata(&source).ok().map(|meta| (source, meta))
+                }
+                // Only the planned entries are inside new entries.
+                _ if ancestor != path => None,
+                Planned::Copy(source, meta) => Some((source.clone(), meta.clone())),
+                Planned::Dir => {
+                    let parent = path.ancestors().skip(1).find_map(|p| metadata(p).ok());
+                    parent.map(|meta| (path.to_path_buf(), meta))
+                }
+                Planned::File => None,
+            };
+        }
+
+        symlink_metadata(path)
+            .ok()
+            .map(|meta| (path.to_path_buf(), meta))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.created.contains_key(path) || self.lookup(path).is_some()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.lookup(path) {
+            Some((source, meta)) if meta.file_type().is_symlink() => source.is_dir(),
+            Some((_, meta)) => meta.is_dir(),
+            None => false,
+        }
+    }
+
+    /// Returns the entries in the directory `dir` in the planned state.
+    fn children(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut children: Vec<PathBuf> = match self.lookup(dir) {
+            Some((source, _)) => read_dir(source)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|entry| dir.join(entry.file_name()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => vec![],
+        };
+        children.extend(
+            self.created
+                .keys()
+                .filter(|p| p.parent() == Some(dir))
+                .cloned(),
+        );
+        children.sort();
+        children.dedup();
+        children.retain(|child| self.exists(child));
+        children
+    }
+
+    /// Fails like the filesystem if the parent directory of `path` is
+    /// missing.
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !self.is_dir(parent) => {
+                match self.exists(parent) {
+                    true => Err(ErrorKind::NotADirectory.into()),
+                    false => Err(ErrorKind::NotFound.into()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Plans deleting the file, or the directory with its content.
+    fn delete_tree(&mut self, path: &Path) -> io::Result<()> {
+        let meta = match self.lookup(path) {
+            Some((_, meta)) => meta,
+            None if self.exists(path) => {
+                self.push_delete(path, 0);
+                return Ok(());
+            }
+            None => return Err(ErrorKind::NotFound.into()),
+        };
+
+        if meta.is_dir() {
+            for child in self.children(path) {
+                self.delete_tree(&child)?;
+            }
+            self.push_delete(path, 0);
+        } else {
+            self.push_delete(path, meta.len());
+        }
+
+        Ok(())
+    }
+
+    fn push_delete(&mut self, path: &Path, size: u64) {
+        self.push(Action::Delete {
+            path: path.to_path_buf(),
+            size,
+        });
+    }
+}
+
+impl Exec for Planner {
+    fn lookup(&self, path: &Path) -> Option<(PathBuf, Metadata)> {
+        self.state.lock().unwrap().lookup(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().exists(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().is_dir(path)
+    }
+
+    fn threads(&self, _threads: Option<usize>) -> usize {
+        1
+    }
+
+    fn resolved(&self, from: &Path, to: &Path, action: ConflictAction) {
+        self.state.lock().unwrap().conflicts.push(Conflict {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            action,
+        });
+    }
+
+    fn create_dir(&self, path: &Path, mode: Option<u32>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.exists(path) {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        state.check_parent(path)?;
+
+        state.push(Action::CreateDir {
+            path: path.to_path_buf(),
+            mode,
+        });
+        Ok(())
+    }
+
+    fn create_file(&self, path: &Path, truncate: bool) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.check_parent(path)?;
+        if state.is_dir(path) {
+            return Err(io::Error::other("is a directory"));
+        }
+
+        if truncate && state.exists(path) {
+            state.conflicts.push(Conflict {
+                from: path.to_path_buf(),
+                to: path.to_path_buf(),
+                action: ConflictAction::Overwrite,
+            });
+        }
+        state.push(Action::CreateFile {
+            path: path.to_path_buf(),
+            truncate,
+        });
+        Ok(())
+    }
+
+    fn copy_dir(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.exists(to) {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        state.check_parent(to)?;
+
+        state.push(Action::Copy {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            size: 0,
+        });
+        Ok(())
+    }
+
+    fn copy_file(
+        &self,
+        op: &'static str,
+        entry: &Entry,
+        to: &Path,
+        _reflink: Reflink,
+        _monitor: &Monitor,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.check_parent(to).between(op, entry.path(), to)?;
+
+        let size = match entry.is_file() {
+            true => entry.metadata().len(),
+            false => 0,
+        };
+        state.push(Action::Copy {
+            from: entry.path().to_path_buf(),
+            to: to.to_path_buf(),
+            size,
+        });
+        Ok(())
+    }
+
+    /// Metadata is preserved by the execution of the copies.
+    fn preserve(
+        &self,
+        _op: &'static str,
+        _preserve: Preserve,
+        _from: &Path,
+        _from_meta: &Metadata,
+        _to: &Path,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let (source, meta) = match state.lookup(from) {
+            Some(existing) => existing,
+            None => return Err(ErrorKind::NotFound.into()),
+        };
+        state.check_parent(to)?;
+        if state.exists(to) {
+            let to_dir = state.lookup(to).is_some_and(|(_, meta)| meta.is_dir());
+            match (meta.is_dir(), to_dir) {
+                (true, false) => return Err(ErrorKind::NotADirectory.into()),
+                (false, true) => return Err(io::Error::other("is a directory")),
+                (true, true) if !state.children(to).is_empty() => {
+                    return Err(ErrorKind::DirectoryNotEmpty.into())
+                }
+                _ => {}
+            }
+        }
+        if !same_device(&source, to) {
+            return Err(ErrorKind::CrossesDevices.into());
+        }
+
+        state.push(Action::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            size: tree_size(&source),
+        });
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match state.lookup(path) {
+            Some((_, meta)) if meta.is_dir() => Err(io::Error::other("is a directory")),
+            Some((_, meta)) => {
+                state.push_delete(path, meta.len());
+                Ok(())
+            }
+            None if state.exists(path) => {
+                state.push_delete(path, 0);
+                Ok(())
+            }
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.exists(path) {
+            return Err(ErrorKind::NotFound.into());
+        }
+        if !state.children(path).is_empty() {
+            return Err(ErrorKind::DirectoryNotEmpty.into());
+        }
+
+        state.push_delete(path, 0);
+        Ok(())
+    }
+
+    fn remove_tree(&self, op: &'static str, path: &Path) -> Result<()> {
+        self.state.lock().unwrap().delete_tree(path).at(op, path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn trash(&self, path: &Path) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let source = match state.lookup(path) {
+            Some((source, _)) => source,
+            None => return Err(io::Error::from(ErrorKind::NotFound)).at("trash", path),
+        };
+
+        state.push(Action::Trash {
+            path: path.to_path_buf(),
+            size: tree_size(&source),
+        });
+        Ok(())
+    }
+}
+
+/// Returns the size of the files in the tree on disk.
+fn tree_size(path: &Path) -> u64 {
+    walk(path)
+        .flatten()
+        .filter(Entry::is_file)
+        .map(|entry| entry.metadata().len())
+        .sum()
+}
+
+/// Returns `true` if `from` can be renamed to `to`: the closest existing
+/// parent of `to` is on the same filesystem.
+#[cfg(unix)]
+fn same_device(from: &Path, to: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let parent = to.ancestors().skip(1).find_map(|p| metadata(p).ok());
+    match (symlink_metadata(from), parent) {
+        (Ok(from), Some(parent)) => from.dev() == parent.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_device(_from: &Path, _to: &Path) -> bool {
+    true
+}
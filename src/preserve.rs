@@ -0,0 +1,258 @@
+use crate::error::{Result, ResultExt};
+use std::{fs::Metadata, io, path::Path};
+
+/// Set of metadata that is carried over from the source to the copy.
+///
+/// By default nothing is preserved explicitly (regular files still keep
+/// their permission bits). [`Preserve::all`] is the equivalent of `cp -a`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preserve {
+    mode: bool,
+    timestamps: bool,
+    ownership: bool,
+    xattrs: bool,
+}
+
+impl Preserve {
+    /// Preserves nothing.
+    pub fn none() -> Preserve {
+        Preserve::default()
+    }
+
+    /// Preserves permissions, timestamps, ownership and extended attributes.
+    pub fn all() -> Preserve {
+        Preserve {
+            mode: true,
+            timestamps: true,
+            ownership: true,
+            xattrs: true,
+        }
+    }
+
+    /// Preserves permission bits of files and directories.
+    pub fn mode(mut self, yes: bool) -> Preserve {
+        self.mode = yes;
+        self
+    }
+
+    /// Preserves access and modification times.
+    pub fn timestamps(mut self, yes: bool) -> Preserve {
+        self.timestamps = yes;
+        self
+    }
+
+    /// Preserves owner and group, if the current process is permitted to
+    /// change them. Does nothing on non-Unix platforms.
+    pub fn ownership(mut self, yes: bool) -> Preserve {
+        self.ownership = yes;
+        self
+    }
+
+    /// Preserves extended attributes, if the destination filesystem supports
+    /// them. Does nothing on platforms other than Linux.
+    pub fn xattrs(mut self, yes: bool) -> Preserve {
+        self.xattrs = yes;
+        self
+    }
+
+    /// Returns `true` if nothing is preserved.
+    pub fn is_none(&self) -> bool {
+        *self == Preserve::none()
+    }
+
+    /// Copies the selected metadata of `from` to `to`. Symlinks are not
+    /// followed.
+    pub(crate) fn apply(
+        &self,
+        op: &'static str,
+        from: &Path,
+        from_meta: &Metadata,
+        to: &Path,
+    ) -> Result<()> {
+        let is_symlink = from_meta.file_type().is_symlink();
+
+        // Ownership goes first, as changing it can reset the setuid bits.
+        if self.ownership {
+            sys::set_ownership(to, from_meta).between(op, from, to)?;
+        }
+        if self.xattrs {
+            sys::copy_xattrs(from, to).between(op, from, to)?;
+        }
+        // Symlinks have no permissions of their own.
+        if self.mode && !is_symlink {
+            std::fs::set_permissions(to, from_meta.permissions()).between(op, from, to)?;
+        }
+        if self.timestamps {
+            sys::set_times(to, from_meta).between(op, from, to)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `Ok` for errors meaning the attribute can't be set by the
+/// current process or isn't supported by the filesystem.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn permitted(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::permitted;
+    use std::{
+        ffi::CString,
+        fs::Metadata,
+        io,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{lchown, MetadataExt},
+        },
+        path::Path,
+    };
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    pub fn set_ownership(to: &Path, from_meta: &Metadata) -> io::Result<()> {
+        permitted(lchown(to, Some(from_meta.uid()), Some(from_meta.gid())))
+    }
+
+    pub fn set_times(to: &Path, from_meta: &Metadata) -> io::Result<()> {
+        let to = c_path(to)?;
+        let times = [
+            libc::timespec {
+                tv_sec: from_meta.atime() as libc::time_t,
+                tv_nsec: from_meta.atime_nsec() as _,
+            },
+            libc::timespec {
+                tv_sec: from_meta.mtime() as libc::time_t,
+                tv_nsec: from_meta.mtime_nsec() as _,
+            },
+        ];
+
+        // SAFETY: `to` is a valid C string and `times` has two elements.
+        let res = unsafe {
+            libc::utimensat(
+                libc::AT_FDCWD,
+                to.as_ptr(),
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn copy_xattrs(from: &Path, to: &Path) -> io::Result<()> {
+        let from = c_path(from)?;
+        let to = c_path(to)?;
+
+        let names = read_buf(|buf, len| unsafe {
+            // SAFETY: `buf` points to `len` writable bytes or is null with
+            // `len == 0`.
+            libc::llistxattr(from.as_ptr(), buf as *mut libc::c_char, len)
+        });
+        let names = match names {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(()),
+            res => res?,
+        };
+
+        for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+            let name = CString::new(name).unwrap();
+            let value = read_buf(|buf, len| unsafe {
+                // SAFETY: see above.
+                libc::lgetxattr(from.as_ptr(), name.as_ptr(), buf as *mut libc::c_void, len)
+            })?;
+
+            // SAFETY: `value` is a valid buffer of `value.len()` bytes.
+            let res = unsafe {
+                libc::lsetxattr(
+                    to.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr() as *const libc::c_void,
+                    value.len(),
+                    0,
+                )
+            };
+
+            if res != 0 {
+                permitted(Err(io::Error::last_os_error()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls a `*xattr` function twice: to get the size of the value and to
+    /// read it.
+    #[cfg(target_os = "linux")]
+    fn read_buf<F>(f: F) -> io::Result<Vec<u8>>
+    where
+        F: Fn(*mut u8, usize) -> libc::ssize_t,
+    {
+        loop {
+            let len = f(std::ptr::null_mut(), 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut buf = vec![0; len as usize];
+            let read = f(buf.as_mut_ptr(), buf.len());
+            if read >= 0 {
+                buf.truncate(read as usize);
+                return Ok(buf);
+            }
+
+            // The value has grown between the calls.
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ERANGE) {
+                return Err(err);
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn copy_xattrs(_from: &Path, _to: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::{
+        fs::{File, FileTimes, Metadata},
+        io,
+        path::Path,
+    };
+
+    pub fn set_ownership(_to: &Path, _from_meta: &Metadata) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn set_times(to: &Path, from_meta: &Metadata) -> io::Result<()> {
+        if !from_meta.is_file() {
+            return Ok(());
+        }
+
+        let times = FileTimes::new()
+            .set_accessed(from_meta.accessed()?)
+            .set_modified(from_meta.modified()?);
+        File::options().write(true).open(to)?.set_times(times)
+    }
+
+    pub fn copy_xattrs(_from: &Path, _to: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
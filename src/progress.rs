@@ -0,0 +1,221 @@
+use crate::{
+    cancel::{CancelToken, Summary},
+    engine::CopyMethod,
+    error::{Context, Error},
+    walk::Walk,
+};
+use std::{
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+type Callback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// An event sent to the [`Progress`] callback.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The operation has started. The totals are known only if the
+    /// [`prescan`](Progress::prescan) is enabled.
+    Started {
+        /// Size of all files to process.
+        total_bytes: Option<u64>,
+        /// Number of all entries to process.
+        total_entries: Option<u64>,
+    },
+    /// Processing of the entry has started.
+    EntryStarted(&'a Path),
+    /// A chunk of the current file was copied. A file cloned as a reflink
+    /// is reported as one chunk.
+    BytesCopied(u64),
+    /// The content of the file was copied with the method, sent before
+    /// [`Event::EntryFinished`] of the file.
+    FileCopied(&'a Path, CopyMethod),
+    /// The entry was processed.
+    EntryFinished(&'a Path),
+    /// The operation has failed, this is the last event.
+    Error(&'a Path, &'a Error),
+}
+
+/// Progress callback for long-running operations: [`cp_with`](crate::cp_with),
+/// [`mv_with`](crate::mv_with), [`rm_with`](crate::rm_with),
+/// [`extract_with`](crate::extract_with) and
+/// [`get_size_with`](crate::get_size_with).
+///
+/// The callback must be `Send + Sync`: with more than one thread, see
+/// [`CopyOptions::threads`](crate::CopyOptions::threads), it's called from
+/// several threads, possibly at the same time. It should return quickly, as
+/// the thread calling it waits.
+#[derive(Clone, Default)]
+pub struct Progress {
+    callback: Option<Callback>,
+    prescan: bool,
+}
+
+impl Progress {
+    /// Creates a progress reporter calling `callback` for every event.
+    pub fn new<F>(callback: F) -> Progress
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        Progress {
+            callback: Some(Arc::new(callback)),
+            prescan: false,
+        }
+    }
+
+    /// Walks the trees before the operation starts to count the totals
+    /// sent with [`Event::Started`]. Disabled by default, ignored by
+    /// [`get_size_with`](crate::get_size_with).
+    pub fn prescan(mut self, yes: bool) -> Progress {
+        self.prescan = yes;
+        self
+    }
+
+    /// Returns `true` if there is a callback.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    /// Returns `true` if the totals are counted up front.
+    pub(crate) fn is_prescan(&self) -> bool {
+        self.is_enabled() && self.prescan
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+
+    /// Sends [`Event::Started`], counting the entries of the `walks` if the
+    /// prescan is enabled. Only files are counted if `files_only` is set.
+    /// Errors are left for the operation itself to report.
+    pub(crate) fn start<I>(&self, walks: I, files_only: bool)
+    where
+        I: IntoIterator<Item = Walk>,
+    {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let (mut total_bytes, mut total_entries) = (None, None);
+        if self.prescan {
+            let (mut bytes, mut entries) = (0, 0);
+            for entry in walks.into_iter().flatten().flatten() {
+                if entry.is_file() {
+                    bytes += entry.metadata().len();
+                }
+                if entry.is_file() || !files_only {
+                    entries += 1;
+                }
+            }
+            total_bytes = Some(bytes);
+            total_entries = Some(entries);
+        }
+
+        self.emit(Event::Started {
+            total_bytes,
+            total_entries,
+        });
+    }
+
+    /// Sends [`Event::Error`] if `res` is an error. `path` is used if the
+    /// error has no path of its own.
+    pub(crate) fn check<T>(&self, path: &Path, res: Result<T, Error>) -> Result<T, Error> {
+        if let Err(e) = &res {
+            self.emit(Event::Error(e.from_path().unwrap_or(path), e));
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("callback", &self.callback.is_some())
+            .field("prescan", &self.prescan)
+            .finish()
+    }
+}
+
+/// State of a running operation: sends the progress events, counts what is
+/// completed and checks the cancel token.
+pub(crate) struct Monitor<'a> {
+    progress: &'a Progress,
+    cancel: Option<&'a CancelToken>,
+    entries: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(progress: &'a Progress, cancel: Option<&'a CancelToken>) -> Monitor<'a> {
+        Monitor {
+            progress,
+            cancel,
+            entries: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// A monitor that reports nothing and can't be cancelled.
+    pub fn none() -> Monitor<'static> {
+        static NONE: Progress = Progress {
+            callback: None,
+            prescan: false,
+        };
+        Monitor::new(&NONE, None)
+    }
+
+    pub fn progress(&self) -> &Progress {
+        self.progress
+    }
+
+    pub fn start<I>(&self, walks: I, files_only: bool)
+    where
+        I: IntoIterator<Item = Walk>,
+    {
+        self.progress.start(walks, files_only)
+    }
+
+    pub fn entry_started(&self, path: &Path) {
+        self.progress.emit(Event::EntryStarted(path));
+    }
+
+    pub fn entry_finished(&self, path: &Path) {
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        self.progress.emit(Event::EntryFinished(path));
+    }
+
+    pub fn file_copied(&self, path: &Path, method: CopyMethod) {
+        self.progress.emit(Event::FileCopied(path, method));
+    }
+
+    pub fn bytes_copied(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.progress.emit(Event::BytesCopied(bytes));
+    }
+
+    /// Returns [`Error::Cancelled`] if the token was cancelled. `path` is the
+    /// entry about to be processed.
+    pub fn check_cancel(&self, op: &'static str, path: &Path) -> Result<(), Error> {
+        match self.cancel {
+            Some(cancel) if cancel.is_cancelled() => {
+                let mut ctx = Context::new(op, Some(path), None);
+                ctx.set_summary(Summary {
+                    entries: self.entries.load(Ordering::Relaxed),
+                    bytes: self.bytes.load(Ordering::Relaxed),
+                });
+                Err(Error::Cancelled(ctx))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check<T>(&self, path: &Path, res: Result<T, Error>) -> Result<T, Error> {
+        self.progress.check(path, res)
+    }
+}
@@ -0,0 +1,134 @@
+//! Retention-based cleanup of directories.
+use crate::{
+    error::{Result, ResultExt},
+    exec::{Disk, Exec},
+    get_size, ls_with,
+    options::{LsOptions, PruneOptions, RemoveOptions},
+    progress::Monitor,
+    rm_paths,
+};
+use std::{
+    cmp::Reverse,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Order of the entries for [`prune`], from the entries kept longest to the
+/// entries deleted first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// Newest first, the oldest entries are deleted first.
+    #[default]
+    Modified,
+    /// Names in descending order, so entries named by date like
+    /// `backup-2024-05-01` are deleted oldest first.
+    Name,
+    /// Smallest first, the largest entries are deleted first.
+    Size,
+}
+
+/// An entry of the pruned directory.
+struct Candidate {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+/// Deletes the entries of the directory that are over the limits of the
+/// retention policy from `options`: the number of entries, their total size
+/// and their age. Entries are ordered by [`SortBy`], and once the count or
+/// the size limit is reached, all the following entries are deleted.
+/// `dir` - the path to the directory to prune.
+///
+/// Only the direct children of `dir` accepted by the filter from `options`
+/// are considered, a directory counts as one entry with the size of all its
+/// content. Returns the deleted entries, the first deleted first.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - Param `dir` does not exist or isn't a directory.
+/// - The current process does not have the permission to access to `dir` or
+///   its content.
+/// - Param `dir` or its content is protected, see [`Guard`](crate::Guard).
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{prune, PruneOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./logs");
+///  let options = PruneOptions::new().max_size(5 * 1024 * 1024 * 1024);
+///
+///  let removed = prune(&dir, &options)?;
+/// ```
+pub fn prune(dir: impl AsRef<Path>, options: &PruneOptions) -> Result<Vec<PathBuf>> {
+    prune_dir(&Disk, dir.as_ref(), options)
+}
+
+/// Prunes the directory passed to [`prune`] or plans pruning it.
+pub(crate) fn prune_dir(
+    exec: &dyn Exec,
+    dir: &Path,
+    options: &PruneOptions,
+) -> Result<Vec<PathBuf>> {
+    options.guard.check("prune", dir)?;
+
+    let ls_options = LsOptions::new().filter(options.filter.clone());
+    let mut candidates = vec![];
+    for path in ls_with(dir, &ls_options)? {
+        let (source, meta) = match exec.lookup(&path) {
+            Some(found) => found,
+            None => return Err(io::Error::from(ErrorKind::NotFound)).at("prune", &path),
+        };
+        let modified = meta.modified().at("prune", &path)?;
+        let size = get_size(&source)?;
+        candidates.push(Candidate {
+            path,
+            modified,
+            size,
+        });
+    }
+
+    match options.sort {
+        SortBy::Modified => candidates.sort_by_key(|c| Reverse(c.modified)),
+        SortBy::Name => candidates.sort_by(|a, b| b.path.file_name().cmp(&a.path.file_name())),
+        SortBy::Size => candidates.sort_by_key(|c| c.size),
+    }
+
+    let now = SystemTime::now();
+    let (mut kept, mut total, mut full) = (0, 0, false);
+    let mut removed = vec![];
+    for candidate in candidates {
+        let expired = options.max_age.is_some_and(|age| {
+            now.duration_since(candidate.modified)
+                .is_ok_and(|elapsed| elapsed > age)
+        });
+        if !expired {
+            full = full
+                || options.max_count.is_some_and(|max| kept >= max)
+                || options
+                    .max_size
+                    .is_some_and(|max| total + candidate.size > max);
+        }
+
+        if expired || full {
+            removed.push(candidate.path);
+        } else {
+            kept += 1;
+            total += candidate.size;
+        }
+    }
+    removed.reverse();
+
+    if !removed.is_empty() {
+        let rm_options = RemoveOptions::new().guard(options.guard.clone());
+        rm_paths(exec, &removed, &rm_options, &Monitor::none())?;
+    }
+
+    Ok(removed)
+}
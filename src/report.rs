@@ -0,0 +1,210 @@
+use std::{
+    fs::Metadata,
+    path::{Path, PathBuf},
+};
+
+/// How an entry was moved by [`mv_with`](crate::mv_with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveStrategy {
+    /// The entry was renamed in place, nothing was copied.
+    Rename,
+    /// The entry was copied to the destination and deleted afterwards,
+    /// because the destination is on another filesystem or symlinks are
+    /// followed.
+    CopyAndDelete,
+}
+
+/// What happened to a source path passed to [`mv_with`](crate::mv_with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveStatus {
+    /// Everything accepted by the options was moved.
+    Moved,
+    /// The move failed midway: some of the content is at the destination,
+    /// the rest is still at the starting point.
+    Partial,
+    /// Nothing was moved, or everything was rolled back.
+    Untouched,
+}
+
+/// A file, directory or symlink moved by [`mv_with`](crate::mv_with).
+#[derive(Debug, Clone)]
+pub struct Moved {
+    from: PathBuf,
+    to: PathBuf,
+    strategy: MoveStrategy,
+}
+
+impl Moved {
+    pub(crate) fn new(from: &Path, to: &Path, strategy: MoveStrategy) -> Moved {
+        Moved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            strategy,
+        }
+    }
+
+    /// Returns the path the entry was moved from.
+    pub fn from_path(&self) -> &Path {
+        &self.from
+    }
+
+    /// Returns the path the entry was moved to.
+    pub fn to_path(&self) -> &Path {
+        &self.to
+    }
+
+    /// Returns how the entry was moved.
+    pub fn strategy(&self) -> MoveStrategy {
+        self.strategy
+    }
+}
+
+/// A change made by [`mv_with`](crate::mv_with), in the order the changes
+/// were made, so they can be undone in reverse.
+#[derive(Debug)]
+pub(crate) enum Change {
+    /// The entry was moved as a whole.
+    Moved(Moved),
+    /// The entry was copied, but deleting the source failed midway.
+    Copied(Moved),
+    /// A destination directory was created to move the content into.
+    CreatedDir(PathBuf),
+    /// A source directory was deleted after its content was moved.
+    RemovedDir(PathBuf, Metadata),
+    /// An existing destination was moved aside to be replaced. It's deleted
+    /// once the move is complete, `done` is set when the entry replacing it
+    /// is complete.
+    Replaced {
+        path: PathBuf,
+        aside: PathBuf,
+        done: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Source {
+    path: PathBuf,
+    status: MoveStatus,
+    /// Range of the changes of the source in the journal.
+    start: usize,
+    end: usize,
+}
+
+/// What [`mv_with`](crate::mv_with) has done.
+///
+/// A directory moved as a whole is reported as one entry. A directory that
+/// had to be moved entry by entry (with a filter or when it is merged into
+/// an existing directory) is reported as its moved content.
+///
+/// If `mv_with` fails, the report is attached to the error, see
+/// [`Error::report`](crate::Error::report).
+#[derive(Debug, Default)]
+pub struct MoveReport {
+    journal: Vec<Change>,
+    sources: Vec<Source>,
+}
+
+impl MoveReport {
+    /// Returns the moved entries in the order they were moved.
+    pub fn moved(&self) -> impl Iterator<Item = &Moved> {
+        self.journal.iter().filter_map(|change| match change {
+            Change::Moved(moved) => Some(moved),
+            _ => None,
+        })
+    }
+
+    /// Returns the source paths with their status, in the order they were
+    /// passed.
+    pub fn sources(&self) -> impl Iterator<Item = (&Path, MoveStatus)> {
+        self.sources
+            .iter()
+            .map(|source| (source.path.as_path(), source.status))
+    }
+
+    /// Returns the status of the `source` path.
+    pub fn status(&self, source: &Path) -> Option<MoveStatus> {
+        self.sources().find(|s| s.0 == source).map(|s| s.1)
+    }
+
+    pub(crate) fn record(&mut self, change: Change) {
+        self.journal.push(change);
+    }
+
+    /// Adds a source path, which is untouched until it's started with
+    /// [`begin`](MoveReport::begin).
+    pub(crate) fn add_source(&mut self, path: &Path) {
+        self.sources.push(Source {
+            path: path.to_path_buf(),
+            status: MoveStatus::Untouched,
+            start: 0,
+            end: 0,
+        });
+    }
+
+    /// Starts recording the changes of the source path at `index`.
+    pub(crate) fn begin(&mut self, index: usize) {
+        let len = self.journal.len();
+        let source = &mut self.sources[index];
+        source.start = len;
+        source.end = len;
+    }
+
+    /// Sets the status of the source path at `index` after it was moved or
+    /// the move failed.
+    pub(crate) fn finish(&mut self, index: usize, ok: bool) {
+        let len = self.journal.len();
+        let source = &mut self.sources[index];
+        source.end = len;
+        source.status = match (source.start == len, ok) {
+            (true, _) => MoveStatus::Untouched,
+            (false, true) => MoveStatus::Moved,
+            (false, false) => MoveStatus::Partial,
+        };
+    }
+
+    /// Marks the last replacement of the destination `path` as complete.
+    pub(crate) fn replaced(&mut self, path: &Path) {
+        let replaced = self
+            .journal
+            .iter_mut()
+            .rev()
+            .find_map(|change| match change {
+                Change::Replaced { path: p, done, .. } if p == path => Some(done),
+                _ => None,
+            });
+        if let Some(done) = replaced {
+            *done = true;
+        }
+    }
+
+    /// Returns the replaced destinations with the paths they were moved
+    /// aside to, and whether their replacement is complete.
+    pub(crate) fn replacements(&self) -> impl Iterator<Item = (&Path, &Path, bool)> {
+        self.journal.iter().filter_map(|change| match change {
+            Change::Replaced { path, aside, done } => {
+                Some((path.as_path(), aside.as_path(), *done))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes the last change to undo it. If undoing fails, the change has
+    /// to be put back with [`record`](MoveReport::record).
+    pub(crate) fn pop(&mut self) -> Option<Change> {
+        self.journal.pop()
+    }
+
+    /// Updates the status of the source paths after some of the changes
+    /// were undone.
+    pub(crate) fn rolled_back(&mut self) {
+        let len = self.journal.len();
+
+        for source in self.sources.iter_mut().filter(|s| s.end > len) {
+            source.end = len.max(source.start);
+            source.status = match source.start == source.end {
+                true => MoveStatus::Untouched,
+                false => MoveStatus::Partial,
+            };
+        }
+    }
+}
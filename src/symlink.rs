@@ -0,0 +1,39 @@
+use std::{fs::read_link, io, path::Path};
+
+/// What to do with symlinks found by [`cp_with`](crate::cp_with),
+/// [`mv_with`](crate::mv_with) and [`extract_with`](crate::extract_with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Create a symlink with the same target at the destination. The target
+    /// path is copied as it is, so relative symlinks stay relative.
+    /// The default.
+    #[default]
+    CopyLink,
+    /// Copy the content of the target instead of the symlink. Symlink loops
+    /// are detected and returned as
+    /// [`Error::SymlinkLoop`](crate::Error::SymlinkLoop).
+    Follow,
+    /// Leave symlinks out.
+    Skip,
+    /// Stop with [`Error::Symlink`](crate::Error::Symlink).
+    Error,
+}
+
+/// Creates a symlink at `to` with the same target as the `from` symlink.
+pub(crate) fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    let target = read_link(from)?;
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&target, to)
+    }
+
+    #[cfg(windows)]
+    {
+        if from.metadata().is_ok_and(|meta| meta.is_dir()) {
+            std::os::windows::fs::symlink_dir(&target, to)
+        } else {
+            std::os::windows::fs::symlink_file(&target, to)
+        }
+    }
+}
@@ -0,0 +1,727 @@
+//! Copying and moving of trees, shared by `cp`, `mv` and `extract`.
+use crate::{
+    conflict::{ConflictAction, ConflictPolicy},
+    engine::Reflink,
+    error::{Result, ResultExt},
+    exec::Exec,
+    options::CopyOptions,
+    parallel::{self, Subtree},
+    preserve::Preserve,
+    progress::Monitor,
+    report::{Change, MoveReport, MoveStrategy, Moved},
+    symlink::SymlinkPolicy,
+    utils, walk, Entry,
+};
+use std::{
+    fs::{canonicalize, metadata, Metadata},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Where an entry goes after its conflict is resolved.
+pub enum Target {
+    /// Create the entry at the path.
+    Create(PathBuf),
+    /// Copy the content of the directory into the existing directory.
+    Merge(PathBuf),
+    /// Replace the existing entry of the same kind once the new one is
+    /// complete. A file is copied next to it and renamed over it, a
+    /// directory is swapped with its complete copy.
+    Replace(PathBuf),
+    Skip,
+}
+
+/// Resolves the conflict if the destination `to` of `from` already exists.
+///
+/// An entry is never overwritten by itself or by an entry of another kind:
+/// a directory can only replace a directory, and a file or a symlink only
+/// a file or a symlink. Anything else fails with
+/// [`Error::DestinationExists`](crate::Error::DestinationExists).
+pub fn target(
+    exec: &dyn Exec,
+    op: &'static str,
+    policy: &ConflictPolicy,
+    from: &Path,
+    from_meta: &Metadata,
+    to: PathBuf,
+) -> Result<Target> {
+    let (to_source, to_meta) = match exec.lookup(&to) {
+        Some(existing) => existing,
+        None => return Ok(Target::Create(to)),
+    };
+
+    let action = policy
+        .resolve(from, from_meta, &to_source, &to_meta)
+        .between(op, from, &to)?;
+    exec.resolved(from, &to, action);
+
+    match action {
+        ConflictAction::Skip => Ok(Target::Skip),
+        ConflictAction::Rename => Ok(Target::Create(utils::free_name_by(&to, |p| exec.exists(p)))),
+        ConflictAction::Merge if from_meta.is_dir() && to_meta.is_dir() => Ok(Target::Merge(to)),
+        ConflictAction::Merge | ConflictAction::Error => err!(DestinationExists, op, from, &to),
+        ConflictAction::Overwrite => {
+            let same_file = to_source == to && walk::same_file(from_meta, &to_meta);
+            if same_file || from_meta.is_dir() != to_meta.is_dir() {
+                err!(DestinationExists, op, from, &to)
+            }
+            Ok(Target::Replace(to))
+        }
+    }
+}
+
+/// Renames `path` to a free hidden name next to it, so it can be put back
+/// or deleted once its replacement is complete.
+pub fn move_aside(exec: &dyn Exec, path: &Path) -> io::Result<PathBuf> {
+    let (aside, ()) = utils::create_temp(path, |aside| match exec.exists(aside) {
+        true => Err(ErrorKind::AlreadyExists.into()),
+        false => exec.rename(path, aside),
+    })?;
+    Ok(aside)
+}
+
+/// Puts the complete copy `temp` of `from` in place of the existing
+/// directory `to`, which is deleted afterwards.
+fn swap(exec: &dyn Exec, op: &'static str, from: &Path, temp: &Path, to: &Path) -> Result<()> {
+    let aside = move_aside(exec, to).between(op, from, to)?;
+    if let Err(e) = exec.rename(temp, to) {
+        exec.rename(&aside, to).ok();
+        return Err(e).between(op, from, to);
+    }
+    exec.remove_tree(op, &aside)
+}
+
+/// Checks the destination of [`cp_with`](crate::cp_with) or
+/// [`mv_with`](crate::mv_with) before anything is done. Returns the target
+/// directory if it's missing and has to be created. Errors point to the
+/// destination of the first source, where copying it would have failed.
+pub fn check_target(
+    op: &'static str,
+    from: &[PathBuf],
+    to: &Path,
+    options: &CopyOptions,
+) -> Result<Option<PathBuf>> {
+    let dir = if options.no_target_directory {
+        if from.len() > 1 {
+            err!(TooManyPaths, op, &from[1], to)
+        }
+        match to.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => return Ok(None),
+        }
+    } else {
+        to
+    };
+
+    let first_to = destination(&from[0], to, options);
+    match metadata(dir) {
+        Ok(meta) if meta.is_dir() => Ok(None),
+        Ok(_) => err!(NotADirectory, op, &from[0], &first_to),
+        Err(_) if options.create_target => Ok(Some(dir.to_path_buf())),
+        Err(e) => Err(e).between(op, &from[0], &first_to),
+    }
+}
+
+/// Fails with [`Error::DestinationExists`](crate::Error::DestinationExists)
+/// if the destination `to` of the source `from` is `from` itself or inside
+/// it, where the operation would go on with its own result. The source is
+/// resolved only if symlinks are followed, like the operation does.
+pub fn check_inside(op: &'static str, from: &Path, to: &Path, options: &CopyOptions) -> Result<()> {
+    let follow = options.symlinks == SymlinkPolicy::Follow;
+    if let (Some(from_real), Some(to_real)) = (real_path(from, follow), real_path(to, false)) {
+        if to_real.starts_with(&from_real) {
+            err!(DestinationExists, op, from, to)
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the absolute path of `path` with the symlinks of its parents
+/// resolved, and the last one too if `follow` is set. Missing parents are
+/// taken as they are.
+fn real_path(path: &Path, follow: bool) -> Option<PathBuf> {
+    if follow {
+        if let Ok(real) = canonicalize(path) {
+            return Some(real);
+        }
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = match parent.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => parent,
+            };
+            real_path(parent, true).map(|parent| parent.join(name))
+        }
+        _ => canonicalize(path).ok(),
+    }
+}
+
+/// Returns where the source `path` goes for the `to` path passed by the
+/// user.
+pub fn destination(path: &Path, to: &Path, options: &CopyOptions) -> PathBuf {
+    if options.no_target_directory {
+        to.to_path_buf()
+    } else {
+        to.join(path.file_name().unwrap())
+    }
+}
+
+/// Returns `false` if the entry is a symlink that has to be skipped.
+/// A symlink can only be met with [`SymlinkPolicy::Follow`] if it's broken.
+pub fn accepts_symlink(op: &'static str, policy: SymlinkPolicy, entry: &Entry) -> Result<bool> {
+    if !entry.is_symlink() {
+        return Ok(true);
+    }
+
+    match policy {
+        SymlinkPolicy::CopyLink => Ok(true),
+        SymlinkPolicy::Skip => Ok(false),
+        SymlinkPolicy::Error => err!(Symlink, op, entry.path()),
+        SymlinkPolicy::Follow => metadata(entry.path()).at(op, entry.path()).map(|_| true),
+    }
+}
+
+/// Moves a file or a symlink, renaming it if possible. A followed symlink is
+/// replaced by a copy of its target, like a file on another filesystem.
+pub fn move_file(
+    exec: &dyn Exec,
+    op: &'static str,
+    entry: &Entry,
+    to: &Path,
+    reflink: Reflink,
+    monitor: &Monitor,
+) -> Result<()> {
+    if !entry.path_is_symlink() || entry.is_symlink() {
+        match exec.rename(entry.path(), to) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+            Err(e) => return Err(e).between(op, entry.path(), to),
+        }
+    }
+
+    exec.copy_file(op, entry, to, reflink, monitor)?;
+    exec.preserve(op, Preserve::all(), entry.path(), entry.metadata(), to)?;
+    exec.remove_file(entry.path()).at(op, entry.path())
+}
+
+/// Copies the `from` tree to the `to` path (not into it).
+pub fn copy_tree(
+    exec: &dyn Exec,
+    op: &'static str,
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+    monitor: &Monitor,
+) -> Result<()> {
+    let threads = exec.threads(options.threads);
+    if threads > 1 {
+        return copy_tree_parallel(exec, op, from, to, options, monitor, threads);
+    }
+
+    // Directories on the way to the current entry with their destinations,
+    // indexed by depth. Metadata of a directory is preserved when the
+    // directory is left, as copying the content changes it.
+    let mut dirs: Vec<(Entry, DirCopy)> = vec![];
+    let mut copy = || {
+        let mut entries = walk(from)
+            .op(op)
+            .follow_links(options.symlinks == SymlinkPolicy::Follow)
+            .filter_entries(options.filter.clone());
+
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let depth = entry.depth();
+            leave_dirs(exec, op, &mut dirs, depth, options)?;
+            monitor.check_cancel(op, entry.path())?;
+
+            let entry_to = match depth {
+                0 => to.to_path_buf(),
+                _ => dirs[depth - 1]
+                    .1
+                    .path
+                    .join(entry.path().file_name().unwrap()),
+            };
+
+            match copy_entry(exec, op, &entry, entry_to, options, monitor)? {
+                Some(dir) => dirs.push((entry, dir)),
+                None => entries.skip_current_dir(),
+            }
+        }
+
+        leave_dirs(exec, op, &mut dirs, 0, options)
+    };
+
+    let res = copy();
+    if res.is_err() {
+        // Incomplete copies that were to replace existing directories.
+        for (_, dir) in dirs.iter().filter(|(_, dir)| dir.replaces.is_some()) {
+            exec.remove_tree(op, &dir.path).ok();
+        }
+    }
+    res
+}
+
+/// Same as [`copy_tree`], but copies the subdirectories on `threads`
+/// threads.
+fn copy_tree_parallel(
+    exec: &dyn Exec,
+    op: &'static str,
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+    monitor: &Monitor,
+    threads: usize,
+) -> Result<()> {
+    let follow_links = options.symlinks == SymlinkPolicy::Follow;
+    let root = walk(from)
+        .op(op)
+        .max_depth(0)
+        .follow_links(follow_links)
+        .filter_entries(options.filter.clone())
+        .next();
+    let root = match root {
+        Some(root) => root?,
+        None => return Ok(()),
+    };
+
+    monitor.check_cancel(op, root.path())?;
+    let root_to = match copy_entry(exec, op, &root, to.to_path_buf(), options, monitor)? {
+        Some(root_to) => root_to,
+        None => return Ok(()),
+    };
+
+    // Copies that replace a directory, deleted if the copy fails.
+    let pending = Mutex::new(vec![]);
+    let track = |dir: &DirCopy| {
+        if dir.replaces.is_some() {
+            pending.lock().unwrap().push(dir.path.clone());
+        }
+    };
+    track(&root_to);
+
+    let visit = |dir: &Subtree<DirCopy>, subdirs: &mut Vec<Subtree<DirCopy>>| {
+        let entries = dir
+            .walk(from)
+            .op(op)
+            .follow_links(follow_links)
+            .filter_entries(options.filter.clone());
+
+        for entry in entries {
+            let entry = entry?;
+            monitor.check_cancel(op, entry.path())?;
+            let entry_to = dir.data.path.join(entry.path().file_name().unwrap());
+            if let Some(entry_to) = copy_entry(exec, op, &entry, entry_to, options, monitor)? {
+                track(&entry_to);
+                subdirs.push(dir.child(entry, entry_to));
+            }
+        }
+
+        Ok(())
+    };
+    let finish = |dir: Subtree<DirCopy>| {
+        pending
+            .lock()
+            .unwrap()
+            .retain(|path| *path != dir.data.path);
+        finish_dir(exec, op, &dir.entry, &dir.data, options)
+    };
+
+    let res = parallel::run(threads, Subtree::root(root, root_to), visit, finish);
+    if res.is_err() {
+        for path in pending.into_inner().unwrap() {
+            exec.remove_tree(op, &path).ok();
+        }
+    }
+    res
+}
+
+/// Where the content of a copied directory goes.
+struct DirCopy {
+    path: PathBuf,
+    /// The existing directory replaced by the copy once it's complete.
+    replaces: Option<PathBuf>,
+}
+
+impl DirCopy {
+    fn new(path: PathBuf) -> DirCopy {
+        DirCopy {
+            path,
+            replaces: None,
+        }
+    }
+}
+
+/// Copies the entry to `to`, resolving the conflict first. A directory is
+/// created without its content, the destination for the content is returned
+/// if the directory has to be descended into.
+fn copy_entry(
+    exec: &dyn Exec,
+    op: &'static str,
+    entry: &Entry,
+    to: PathBuf,
+    options: &CopyOptions,
+    monitor: &Monitor,
+) -> Result<Option<DirCopy>> {
+    if !accepts_symlink(op, options.symlinks, entry)? {
+        return Ok(None);
+    }
+
+    let policy = &options.conflict;
+    let (to, replace) = match target(exec, op, policy, entry.path(), entry.metadata(), to)? {
+        Target::Create(to) => (to, false),
+        Target::Replace(to) => (to, true),
+        Target::Merge(to) => return Ok(Some(DirCopy::new(to))),
+        Target::Skip => return Ok(None),
+    };
+
+    monitor.entry_started(entry.path());
+    if entry.is_dir() {
+        let dir = if replace {
+            // The directory is copied next to the existing one and swapped
+            // with it when it's complete.
+            let (temp, ()) = utils::create_temp(&to, |temp| exec.copy_dir(entry.path(), temp))
+                .between(op, entry.path(), &to)?;
+            DirCopy {
+                path: temp,
+                replaces: Some(to),
+            }
+        } else {
+            exec.copy_dir(entry.path(), &to)
+                .between(op, entry.path(), &to)?;
+            DirCopy::new(to)
+        };
+        monitor.entry_finished(entry.path());
+        Ok(Some(dir))
+    } else {
+        exec.copy_file(op, entry, &to, options.reflink, monitor)?;
+        exec.preserve(op, options.preserve, entry.path(), entry.metadata(), &to)?;
+        monitor.entry_finished(entry.path());
+        Ok(None)
+    }
+}
+
+/// Pops the directories deeper than `depth` and finishes them.
+fn leave_dirs(
+    exec: &dyn Exec,
+    op: &'static str,
+    dirs: &mut Vec<(Entry, DirCopy)>,
+    depth: usize,
+    options: &CopyOptions,
+) -> Result<()> {
+    while dirs.len() > depth {
+        let (entry, dir) = dirs.pop().unwrap();
+        finish_dir(exec, op, &entry, &dir, options)?;
+    }
+
+    Ok(())
+}
+
+/// Preserves the metadata of the copied directory after its content is
+/// copied, and puts it in place of the directory it replaces.
+fn finish_dir(
+    exec: &dyn Exec,
+    op: &'static str,
+    entry: &Entry,
+    dir: &DirCopy,
+    options: &CopyOptions,
+) -> Result<()> {
+    exec.preserve(
+        op,
+        options.preserve,
+        entry.path(),
+        entry.metadata(),
+        &dir.path,
+    )?;
+    match &dir.replaces {
+        Some(to) => swap(exec, op, entry.path(), &dir.path, to),
+        None => Ok(()),
+    }
+}
+
+/// A move of the entries of [`mv_with`](crate::mv_with).
+pub struct Mover<'a> {
+    pub exec: &'a dyn Exec,
+    pub op: &'static str,
+    pub options: &'a CopyOptions,
+    pub monitor: &'a Monitor<'a>,
+}
+
+/// A step of a move, see [`Mover::move_entry`].
+enum Step {
+    /// Move the entry to the path.
+    Move(PathBuf, PathBuf),
+    /// Delete the directory after its content was moved, if nothing is left
+    /// in it, and complete the replacement of the destination if there is
+    /// one.
+    Leave(PathBuf, Metadata, Option<PathBuf>),
+}
+
+impl Mover<'_> {
+    /// Moves `from` to the `to` path (not into it). `root` is the source path
+    /// passed by the user, filters are matched relative to it.
+    ///
+    /// An entry is renamed if possible and copied then deleted if it's on
+    /// another filesystem. Every change is recorded in the `report`, so it can
+    /// be undone with [`rollback`]. Directories that have to be moved entry by
+    /// entry are processed with a stack of steps instead of recursion, so
+    /// the depth of the tree doesn't matter.
+    pub fn move_entry(
+        &self,
+        root: &Path,
+        from: &Path,
+        to: &Path,
+        report: &mut MoveReport,
+    ) -> Result<()> {
+        let mut steps = vec![Step::Move(from.to_path_buf(), to.to_path_buf())];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Move(from, to) => self.move_step(root, &from, to, report, &mut steps)?,
+                Step::Leave(from, from_meta, replaced) => {
+                    self.leave(&from, from_meta, report)?;
+                    if let Some(to) = replaced {
+                        report.replaced(&to);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a single entry. The content of a directory that can't be moved
+    /// as a whole is pushed to the `steps`.
+    fn move_step(
+        &self,
+        root: &Path,
+        from: &Path,
+        to: PathBuf,
+        report: &mut MoveReport,
+        steps: &mut Vec<Step>,
+    ) -> Result<()> {
+        let (exec, op, options, monitor) = (self.exec, self.op, self.options, self.monitor);
+        monitor.check_cancel(op, from)?;
+        let from_meta = match exec.lookup(from) {
+            Some((_, meta)) => meta,
+            None => return Err(io::Error::from(ErrorKind::NotFound)).at(op, from),
+        };
+
+        if from_meta.file_type().is_symlink() {
+            match options.symlinks {
+                SymlinkPolicy::Skip => return Ok(()),
+                SymlinkPolicy::Error => err!(Symlink, op, from),
+                SymlinkPolicy::CopyLink | SymlinkPolicy::Follow => {}
+            }
+        }
+
+        // With a filter or skipped symlinks a directory can't be moved as
+        // a whole, as some of its content has to stay. Symlinks inside have to
+        // be checked one by one if they are not allowed.
+        let partial = from_meta.is_dir()
+            && (!options.filter.is_empty()
+                || matches!(options.symlinks, SymlinkPolicy::Skip | SymlinkPolicy::Error));
+
+        let (to, replaced) = match target(exec, op, &options.conflict, from, &from_meta, to)? {
+            Target::Skip => return Ok(()),
+            Target::Merge(to) => return self.push_content(root, from, from_meta, to, None, steps),
+            Target::Create(to) => (to, None),
+            Target::Replace(to) => {
+                // The destination is moved aside, so a rollback can put it
+                // back. It's deleted when the whole move is complete.
+                let aside = move_aside(exec, &to).between(op, from, &to)?;
+                report.record(Change::Replaced {
+                    path: to.clone(),
+                    aside,
+                    done: false,
+                });
+                (to.clone(), Some(to))
+            }
+        };
+
+        if !partial {
+            self.move_whole(from, &from_meta, &to, report)?;
+            if let Some(to) = replaced {
+                report.replaced(&to);
+            }
+            return Ok(());
+        }
+
+        monitor.entry_started(from);
+        exec.create_dir(&to, None).between(op, from, &to)?;
+        report.record(Change::CreatedDir(to.clone()));
+        monitor.entry_finished(from);
+        self.push_content(root, from, from_meta, to, replaced, steps)
+    }
+
+    /// Pushes the steps moving the content of the `from` directory into the
+    /// existing `to` directory, followed by leaving `from`.
+    fn push_content(
+        &self,
+        root: &Path,
+        from: &Path,
+        from_meta: Metadata,
+        to: PathBuf,
+        replaced: Option<PathBuf>,
+        steps: &mut Vec<Step>,
+    ) -> Result<()> {
+        let children = walk(from)
+            .op(self.op)
+            .min_depth(1)
+            .max_depth(1)
+            .filter_entries(self.options.filter.clone())
+            .relative_to(root)
+            .collect::<Result<Vec<Entry>>>()?;
+
+        steps.push(Step::Leave(from.to_path_buf(), from_meta, replaced));
+        // The steps are popped in reverse, so the children are pushed in
+        // reverse to be moved in the order they were found.
+        for child in children.into_iter().rev() {
+            let child_to = to.join(child.path().file_name().unwrap());
+            steps.push(Step::Move(child.into_path(), child_to));
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the `from` directory after its content was moved. The
+    /// directory keeps the skipped content.
+    fn leave(&self, from: &Path, from_meta: Metadata, report: &mut MoveReport) -> Result<()> {
+        match self.exec.remove_dir(from) {
+            Ok(()) => {
+                report.record(Change::RemovedDir(from.to_path_buf(), from_meta));
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => Ok(()),
+            Err(e) => Err(e).at(self.op, from),
+        }
+    }
+
+    /// Moves `from` with its whole content to the `to` path, which doesn't
+    /// exist. Symlinks that are followed have to be
+    /// copied, as renaming would move the symlinks themselves.
+    ///
+    /// If copying fails, the partial copy is deleted and the source is left
+    /// untouched.
+    fn move_whole(
+        &self,
+        from: &Path,
+        from_meta: &Metadata,
+        to: &Path,
+        report: &mut MoveReport,
+    ) -> Result<()> {
+        let (exec, op, options, monitor) = (self.exec, self.op, self.options, self.monitor);
+        if options.symlinks != SymlinkPolicy::Follow || from_meta.is_file() {
+            match exec.rename(from, to) {
+                Ok(()) => {
+                    // Renaming is instant, so the events are sent afterwards.
+                    monitor.entry_started(from);
+                    report.record(Change::Moved(Moved::new(from, to, MoveStrategy::Rename)));
+                    if monitor.progress().is_prescan() {
+                        monitor.bytes_copied(tree_size(to));
+                    }
+                    monitor.entry_finished(from);
+                    return Ok(());
+                }
+                Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+                Err(e) => return Err(e).between(op, from, to),
+            }
+        }
+
+        let existed = exec.exists(to);
+        if let Err(e) = copy_tree(exec, op, from, to, options, monitor) {
+            if !existed && exec.exists(to) {
+                exec.remove_tree(op, to).ok();
+            }
+            return Err(e);
+        }
+
+        let moved = Moved::new(from, to, MoveStrategy::CopyAndDelete);
+        match exec.remove_tree(op, from) {
+            Ok(()) => report.record(Change::Moved(moved)),
+            Err(e) => {
+                report.record(Change::Copied(moved));
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the size of the files in the tree, for the progress of renamed
+/// entries.
+fn tree_size(path: &Path) -> u64 {
+    walk(path)
+        .flatten()
+        .filter(Entry::is_file)
+        .map(|entry| entry.metadata().len())
+        .sum()
+}
+
+/// Undoes the changes recorded in the `report` in reverse order: moves the
+/// entries back, puts back the replaced destinations and deletes the
+/// created directories. Stops on the first change that can't be undone,
+/// leaving it in the report.
+pub fn rollback(exec: &dyn Exec, op: &'static str, report: &mut MoveReport) -> Result<()> {
+    let res = undo_all(exec, op, report);
+    report.rolled_back();
+    res
+}
+
+/// Deletes the destinations replaced by the move, once nothing has to be
+/// undone anymore. A destination whose replacement is missing, because
+/// moving it failed, is put back instead. One whose replacement was moved
+/// only partly is kept where it was moved aside.
+pub fn drop_replaced(exec: &dyn Exec, op: &'static str, report: &MoveReport) -> Result<()> {
+    for (path, aside, done) in report.replacements() {
+        if done {
+            exec.remove_tree(op, aside)?;
+        } else if !exec.exists(path) {
+            exec.rename(aside, path).between(op, aside, path)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn undo_all(exec: &dyn Exec, op: &'static str, report: &mut MoveReport) -> Result<()> {
+    // Entries copied back get all their metadata, existing ones are kept.
+    let restore = CopyOptions::new()
+        .conflict(ConflictPolicy::Skip)
+        .preserve(Preserve::all());
+
+    while let Some(change) = report.pop() {
+        let res = match &change {
+            Change::Moved(moved) if moved.strategy() == MoveStrategy::Rename => exec
+                .rename(moved.to_path(), moved.from_path())
+                .between(op, moved.to_path(), moved.from_path()),
+            Change::Moved(moved) | Change::Copied(moved) => copy_tree(
+                exec,
+                op,
+                moved.to_path(),
+                moved.from_path(),
+                &restore,
+                &Monitor::none(),
+            )
+            .and_then(|()| exec.remove_tree(op, moved.to_path())),
+            Change::CreatedDir(path) => exec.remove_dir(path).at(op, path),
+            Change::Replaced { path, aside, .. } => {
+                exec.rename(aside, path).between(op, aside, path)
+            }
+            Change::RemovedDir(path, meta) => exec
+                .create_dir(path, None)
+                .at(op, path)
+                .and_then(|()| exec.preserve(op, Preserve::all(), path, meta, path)),
+        };
+
+        if let Err(e) = res {
+            report.record(change);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
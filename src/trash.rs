@@ -0,0 +1,557 @@
+//! Trash following the freedesktop.org Trash specification.
+use crate::{
+    batch::{self, ErrorMode},
+    error::{Result, ResultExt},
+    exec::{Disk, Exec},
+    options::{CopyOptions, TrashOptions},
+    preserve::Preserve,
+    progress::Monitor,
+    transfer, utils,
+};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, read_dir, read_to_string, remove_file, rename, symlink_metadata, DirBuilder},
+    io::{ErrorKind, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+};
+
+/// A file or directory in the trash, returned by [`list_trash`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashItem {
+    trash: PathBuf,
+    name: OsString,
+    original_path: PathBuf,
+    deletion_date: String,
+}
+
+impl TrashItem {
+    /// Returns the path the item was deleted from.
+    pub fn original_path(&self) -> &Path {
+        &self.original_path
+    }
+
+    /// Returns the date and time of deletion in the local time zone, in the
+    /// `YYYY-MM-DDThh:mm:ss` format.
+    pub fn deletion_date(&self) -> &str {
+        &self.deletion_date
+    }
+
+    /// Returns the path of the item inside the trash.
+    pub fn trashed_path(&self) -> PathBuf {
+        self.trash.join("files").join(&self.name)
+    }
+
+    fn info_path(&self) -> PathBuf {
+        info_path(&self.trash, &self.name)
+    }
+}
+
+/// Moves files and directories to the trash instead of deleting them.
+/// `paths` - takes a list of paths of what you want to move to the trash.
+///
+/// Items on the home filesystem go to the home trash
+/// (`$XDG_DATA_HOME/Trash`), items on other filesystems go to the trash of
+/// their filesystem (`.Trash/$uid` or `.Trash-$uid` at its top directory).
+/// If the trash of the filesystem can't be used, items are copied to the
+/// home trash. Only available on Linux.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - Param `paths` contains file or directory does not exist.
+/// - The current process does not have the permission to access to input
+///   params or to the trash.
+/// - Param `paths` is empty.
+/// - Param `paths` contains a root, the home directory or a mount point, see
+///   [`Guard`](crate::Guard).
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::trash;
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let file = std::path::PathBuf::from(r"./file.txt");
+///
+///  trash(&vec![&dir, &file])?;
+/// ```
+pub fn trash(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    trash_with(paths, &TrashOptions::new())
+}
+
+/// Same as [`trash`], but with `options`: a [`Guard`](crate::Guard) for the
+/// trashed paths.
+///
+/// # Errors
+///
+/// See [`trash`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{trash_with, Guard, TrashOptions};
+///
+///  let dir = std::path::PathBuf::from(r"./dir");
+///  let options = TrashOptions::new().guard(Guard::new().protect("./dir/keep"));
+///
+///  trash_with(&vec![&dir], &options)?;
+/// ```
+pub fn trash_with(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &TrashOptions,
+) -> Result<()> {
+    trash_paths(&Disk, &utils::paths(paths), options)
+}
+
+/// Moves the paths passed to [`trash_with`] to the trash or plans moving
+/// them.
+pub(crate) fn trash_paths(
+    exec: &dyn Exec,
+    paths: &[PathBuf],
+    options: &TrashOptions,
+) -> Result<()> {
+    if paths.is_empty() {
+        err!(EmptyInput, "trash")
+    }
+
+    batch::for_each_guarded("trash", paths, ErrorMode::Strict, &options.guard, |path| {
+        exec.trash(path)
+    })
+}
+
+/// Moves a path passed to [`trash`] to the trash.
+pub(crate) fn trash_one(path: &Path) -> Result<()> {
+    let meta = symlink_metadata(path).at("trash", path)?;
+    let path = absolute(path)?;
+    let home = home_trash()?;
+
+    let (trash, original) = match topdir(&path, meta.dev())? {
+        Some(topdir) => match volume_trash(&topdir, true) {
+            // Paths in a volume trash are relative to its top directory.
+            Some(trash) => (trash, path.strip_prefix(&topdir).unwrap().to_path_buf()),
+            None => (home, path.clone()),
+        },
+        None => (home, path.clone()),
+    };
+
+    for dir in ["files", "info"] {
+        create_private_dir(&trash.join(dir)).at("trash", &trash)?;
+    }
+
+    let (name, mut info) = reserve_name(&trash, &path)?;
+    let text = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(original.as_os_str()),
+        now()
+    );
+    info.write_all(text.as_bytes())
+        .at("trash", &info_path(&trash, &name))?;
+
+    let to = trash.join("files").join(&name);
+    if let Err(e) = move_path(&path, &to) {
+        remove_file(info_path(&trash, &name)).ok();
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Returns the items in the home trash and in the trashes of the mounted
+/// filesystems. Only available on Linux.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - The current process does not have the permission to access the trash.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::list_trash;
+///
+///  for item in list_trash()? {
+///      println!("{:?} {}", item.original_path(), item.deletion_date());
+///  }
+/// ```
+pub fn list_trash() -> Result<Vec<TrashItem>> {
+    list_trash_with(&TrashOptions::new())
+}
+
+/// Same as [`list_trash`], but with `options`: only the home trash is listed
+/// with [`TrashOptions::home_only`].
+///
+/// # Errors
+///
+/// See [`list_trash`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{list_trash_with, TrashOptions};
+///
+///  let items = list_trash_with(&TrashOptions::new().home_only(true))?;
+/// ```
+pub fn list_trash_with(options: &TrashOptions) -> Result<Vec<TrashItem>> {
+    let mut items = vec![];
+
+    for (trash, topdir) in trashes(options)? {
+        let info_dir = trash.join("info");
+        let entries = match read_dir(&info_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).at("list_trash", &info_dir),
+        };
+
+        for entry in entries {
+            let info = entry.at("list_trash", &info_dir)?.path();
+            if info.extension() != Some(OsStr::new("trashinfo")) {
+                continue;
+            }
+            // Files that don't follow the specification are ignored.
+            if let Some(item) = parse_info(&trash, topdir.as_deref(), &info) {
+                items.push(item);
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+/// Moves the item from the trash back to its original path.
+/// `item` - the item returned by [`list_trash`]. Only available on Linux.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - The original path is taken by another file or directory.
+/// - The parent directory of the original path does not exist.
+/// - The item is not in the trash anymore.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{list_trash, restore};
+///
+///  for item in list_trash()? {
+///      if item.original_path().ends_with("file.txt") {
+///          restore(&item)?;
+///      }
+///  }
+/// ```
+pub fn restore(item: &TrashItem) -> Result<()> {
+    let from = item.trashed_path();
+    let to = item.original_path();
+
+    if symlink_metadata(to).is_ok() {
+        err!(DestinationExists, "restore", &from, to)
+    }
+    symlink_metadata(&from).at("restore", &from)?;
+
+    move_path(&from, to)?;
+    remove_file(item.info_path()).at("restore", &item.info_path())
+}
+
+/// Deletes all items in the home trash and in the trashes of the mounted
+/// filesystems. Only available on Linux.
+///
+/// # Errors
+///
+/// This function will return an error in the following situations, but is not
+/// limited to just these case:
+///
+/// - The current process does not have the permission to delete some item.
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::empty_trash;
+///
+///  empty_trash()?;
+/// ```
+pub fn empty_trash() -> Result<()> {
+    empty_trash_with(&TrashOptions::new())
+}
+
+/// Same as [`empty_trash`], but with `options`: only the home trash is
+/// emptied with [`TrashOptions::home_only`].
+///
+/// # Errors
+///
+/// See [`empty_trash`].
+///
+/// # Example
+///
+/// ```rust,ignore
+///  extern crate rfm;
+///  use rfm::{empty_trash_with, TrashOptions};
+///
+///  empty_trash_with(&TrashOptions::new().home_only(true))?;
+/// ```
+pub fn empty_trash_with(options: &TrashOptions) -> Result<()> {
+    for (trash, _) in trashes(options)? {
+        // The info file goes last, so an item is never lost without trace.
+        for dir in ["files", "info"] {
+            let dir = trash.join(dir);
+            let entries = match read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).at("empty_trash", &dir),
+            };
+            for entry in entries {
+                let entry = entry.at("empty_trash", &dir)?;
+                Disk.remove_tree("empty_trash", &entry.path())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the home trash, `$XDG_DATA_HOME/Trash`.
+fn home_trash() -> Result<PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/share"),
+            None => err!(NotFound, "trash"),
+        },
+    };
+
+    Ok(data_home.join("Trash"))
+}
+
+/// Returns the trash of the filesystem with the `topdir` top directory:
+/// `$topdir/.Trash/$uid` if `$topdir/.Trash` is a sticky directory, or
+/// `$topdir/.Trash-$uid`, which is created if `create` is set.
+fn volume_trash(topdir: &Path, create: bool) -> Option<PathBuf> {
+    // SAFETY: `getuid` has no preconditions and never fails.
+    let uid = unsafe { libc::getuid() };
+
+    let shared = topdir.join(".Trash");
+    if let Ok(meta) = symlink_metadata(&shared) {
+        let sticky = meta.permissions().mode() & libc::S_ISVTX != 0;
+        if meta.is_dir() && sticky {
+            let trash = shared.join(uid.to_string());
+            if trash.is_dir() || (create && create_private_dir(&trash).is_ok()) {
+                return Some(trash);
+            }
+        }
+    }
+
+    let trash = topdir.join(format!(".Trash-{}", uid));
+    match symlink_metadata(&trash) {
+        Ok(meta) if meta.is_dir() => Some(trash),
+        Err(_) if create && create_private_dir(&trash).is_ok() => Some(trash),
+        _ => None,
+    }
+}
+
+/// Returns the home trash and, unless `options` say otherwise, the trashes
+/// of the mounted filesystems with their top directories.
+fn trashes(options: &TrashOptions) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let home = home_trash()?;
+    let mut trashes = vec![(home.clone(), None)];
+    if options.home_only {
+        return Ok(trashes);
+    }
+
+    for topdir in utils::mount_points() {
+        if let Some(trash) = volume_trash(&topdir, false) {
+            if trash != home && !trashes.iter().any(|(t, _)| *t == trash) {
+                trashes.push((trash, Some(topdir)));
+            }
+        }
+    }
+
+    Ok(trashes)
+}
+
+/// Returns the top directory of the filesystem `path` is on, or `None` if
+/// it's the filesystem of the home trash.
+fn topdir(path: &Path, dev: u64) -> Result<Option<PathBuf>> {
+    let home = home_trash()?;
+    let home_dev = home
+        .ancestors()
+        .find_map(|p| symlink_metadata(p).ok())
+        .map(|meta| meta.dev());
+    if home_dev == Some(dev) {
+        return Ok(None);
+    }
+
+    let mut topdir = path;
+    for parent in path.ancestors().skip(1) {
+        match symlink_metadata(parent) {
+            Ok(meta) if meta.dev() == dev => topdir = parent,
+            _ => break,
+        }
+    }
+
+    Ok(Some(topdir.to_path_buf()))
+}
+
+/// Creates the info file for a free name in the trash. Creating the file
+/// exclusively reserves the name.
+fn reserve_name(trash: &Path, path: &Path) -> Result<(OsString, fs::File)> {
+    let name = path.file_name().unwrap_or_default();
+    let mut candidate = PathBuf::from(name);
+
+    loop {
+        let name = candidate.as_os_str().to_os_string();
+        let info = info_path(trash, &name);
+        let taken = symlink_metadata(trash.join("files").join(&name)).is_ok();
+        if !taken {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info)
+            {
+                Ok(file) => return Ok((name, file)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e).at("trash", &info),
+            }
+        }
+
+        candidate = utils::free_name_by(&candidate, |p| {
+            let name = p.as_os_str();
+            info_path(trash, name).exists() || trash.join("files").join(name).exists()
+        });
+    }
+}
+
+fn info_path(trash: &Path, name: &OsStr) -> PathBuf {
+    let mut file = name.to_os_string();
+    file.push(".trashinfo");
+    trash.join("info").join(file)
+}
+
+fn parse_info(trash: &Path, topdir: Option<&Path>, info: &Path) -> Option<TrashItem> {
+    let text = read_to_string(info).ok()?;
+    let mut lines = text.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+
+    let (mut path, mut date) = (None, None);
+    for line in lines {
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(PathBuf::from(OsString::from_vec(decode(value)?)));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            date = Some(value.to_string());
+        }
+    }
+
+    let path = path?;
+    let original_path = match topdir {
+        Some(topdir) if path.is_relative() => topdir.join(path),
+        _ => path,
+    };
+
+    Some(TrashItem {
+        trash: trash.to_path_buf(),
+        name: info.file_stem()?.to_os_string(),
+        original_path,
+        deletion_date: date?,
+    })
+}
+
+/// Returns the absolute path without resolving the last component, so
+/// a symlink is trashed itself.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let name = match path.file_name() {
+        Some(name) => name,
+        None => err!(NotFound, "trash", path),
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    Ok(parent.canonicalize().at("trash", path)?.join(name))
+}
+
+/// Renames the entry, or copies and deletes it if it's on another
+/// filesystem.
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    match rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let options = CopyOptions::new().preserve(Preserve::all());
+            transfer::copy_tree(&Disk, "trash", from, to, &options, &Monitor::none())?;
+            Disk.remove_tree("trash", from)
+        }
+        Err(e) => Err(e).between("trash", from, to),
+    }
+}
+
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(path)
+}
+
+/// Returns the current local time in the `YYYY-MM-DDThh:mm:ss` format.
+fn now() -> String {
+    // SAFETY: `tm` is fully written by `localtime_r` before it's read.
+    unsafe {
+        let time = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&time, &mut tm);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+/// Percent-encodes the path as required for the `Path` key.
+fn encode(path: &OsStr) -> String {
+    let mut encoded = String::new();
+    for &b in path.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn decode(value: &str) -> Option<Vec<u8>> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Some(decoded)
+}
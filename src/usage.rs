@@ -0,0 +1,264 @@
+use crate::walk::Entry;
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// How [`get_size_with`](crate::get_size_with) measures the entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMode {
+    /// The length of every entry, directories included, except the root
+    /// directory. The default.
+    #[default]
+    Length,
+    /// The length of regular files only, like `du --apparent-size`.
+    Apparent,
+    /// The space allocated on disk for every entry, the root directory
+    /// included, like `du`. Sparse files count only their allocated blocks.
+    /// On systems without block counts, it's the same as
+    /// [`SizeMode::Length`].
+    DiskUsage,
+}
+
+/// Size and number of entries of a tree, returned by
+/// [`get_usage_with`](crate::get_usage_with).
+///
+/// The root directory isn't counted as a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub(crate) bytes: u64,
+    pub(crate) files: u64,
+    pub(crate) dirs: u64,
+    pub(crate) symlinks: u64,
+}
+
+impl Usage {
+    /// Size in bytes, measured by the [`SizeMode`].
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of regular files.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of directories.
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+
+    /// Number of symlinks.
+    pub fn symlinks(&self) -> u64 {
+        self.symlinks
+    }
+
+    fn add(&mut self, other: Usage) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.symlinks += other.symlinks;
+    }
+}
+
+/// A directory in the tree returned by [`du_with`](crate::du_with), with the
+/// total size and number of files of its whole content.
+///
+/// With the `serde` feature the tree can be serialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuNode {
+    path: PathBuf,
+    size: u64,
+    file_count: u64,
+    children: Vec<DuNode>,
+}
+
+impl DuNode {
+    pub(crate) fn new(path: &Path) -> DuNode {
+        DuNode {
+            path: path.to_path_buf(),
+            size: 0,
+            file_count: 0,
+            children: vec![],
+        }
+    }
+
+    /// Path to the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size of the whole content in bytes, measured by the [`SizeMode`].
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Number of regular files in the whole content.
+    pub fn file_count(&self) -> u64 {
+        self.file_count
+    }
+
+    /// Subdirectories down to the depth passed to [`du`](crate::du), in the
+    /// order they were found.
+    pub fn children(&self) -> &[DuNode] {
+        &self.children
+    }
+
+    /// Sorts the subdirectories on every level, the largest first.
+    pub fn sort_by_size(&mut self) {
+        self.children.sort_by_key(|child| Reverse(child.size));
+        for child in &mut self.children {
+            child.sort_by_size();
+        }
+    }
+
+    /// Returns the node and all the nodes below it, every node before its
+    /// children.
+    pub fn nodes(&self) -> Vec<&DuNode> {
+        let mut nodes = vec![self];
+        for child in &self.children {
+            nodes.extend(child.nodes());
+        }
+        nodes
+    }
+
+    fn add(&mut self, usage: Usage) {
+        self.size += usage.bytes;
+        self.file_count += usage.files;
+    }
+}
+
+/// Builds the [`DuNode`] tree from the entries of a walk, passed to
+/// [`add`](DuTree::add) in the walk order.
+pub(crate) struct DuTree {
+    depth: usize,
+    // Nodes of the directories containing the current entry, with their
+    // depth.
+    open: Vec<(usize, DuNode)>,
+}
+
+impl DuTree {
+    /// Creates a tree with the nodes down to `depth`.
+    pub fn new(depth: usize) -> DuTree {
+        DuTree {
+            depth,
+            open: vec![],
+        }
+    }
+
+    /// Adds the entry with the `usage` it was counted with.
+    pub fn add(&mut self, entry: &Entry, usage: Usage) {
+        let depth = entry.depth();
+        while self.open.last().is_some_and(|(d, _)| *d >= depth) {
+            self.close();
+        }
+
+        for (_, node) in &mut self.open {
+            node.add(usage);
+        }
+        if depth == 0 || (entry.is_dir() && depth <= self.depth) {
+            let mut node = DuNode::new(entry.path());
+            node.add(usage);
+            self.open.push((depth, node));
+        }
+    }
+
+    /// Returns the root node, or `None` if no entries were added.
+    pub fn finish(mut self) -> Option<DuNode> {
+        while self.open.len() > 1 {
+            self.close();
+        }
+        self.open.pop().map(|(_, node)| node)
+    }
+
+    /// Moves the last open node to the children of its parent.
+    fn close(&mut self) {
+        if let Some((_, node)) = self.open.pop() {
+            if let Some((_, parent)) = self.open.last_mut() {
+                parent.children.push(node);
+            }
+        }
+    }
+}
+
+/// Adds up the usage of the entries of a walk.
+pub(crate) struct Counter {
+    mode: SizeMode,
+    dedupe_hard_links: bool,
+    // Files with more than one link counted so far, by device and inode.
+    linked: HashSet<(u64, u64)>,
+    usage: Usage,
+}
+
+impl Counter {
+    pub fn new(mode: SizeMode, dedupe_hard_links: bool) -> Counter {
+        Counter {
+            mode,
+            dedupe_hard_links,
+            linked: HashSet::new(),
+            usage: Usage::default(),
+        }
+    }
+
+    /// Counts the entry, returns its own usage.
+    pub fn add(&mut self, entry: &Entry) -> Usage {
+        let mut added = Usage::default();
+        if self.dedupe_hard_links && !entry.is_dir() && !self.is_first_link(entry) {
+            return added;
+        }
+
+        let is_root = entry.depth() == 0;
+        if entry.is_dir() {
+            added.dirs = u64::from(!is_root);
+        } else if entry.is_symlink() {
+            added.symlinks = 1;
+        } else if entry.is_file() {
+            added.files = 1;
+        }
+
+        let len = entry.metadata().len();
+        added.bytes = match self.mode {
+            SizeMode::Length if is_root && entry.is_dir() => 0,
+            SizeMode::Length => len,
+            SizeMode::Apparent if entry.is_file() => len,
+            SizeMode::Apparent => 0,
+            SizeMode::DiskUsage => allocated(entry).unwrap_or(len),
+        };
+
+        self.usage.add(added);
+        added
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Returns `false` if another link to the same file was counted.
+    #[cfg(unix)]
+    fn is_first_link(&mut self, entry: &Entry) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = entry.metadata();
+        meta.nlink() < 2 || self.linked.insert((meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn is_first_link(&mut self, _entry: &Entry) -> bool {
+        true
+    }
+}
+
+/// Returns the space allocated on disk for the entry, if it's known.
+#[cfg(unix)]
+fn allocated(entry: &Entry) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(entry.metadata().blocks() * 512)
+}
+
+#[cfg(not(unix))]
+fn allocated(_entry: &Entry) -> Option<u64> {
+    None
+}
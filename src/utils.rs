@@ -1,51 +1,151 @@
+use crate::{
+    batch,
+    error::{Result, ResultExt},
+    exec::{self, Exec},
+    options::{MkdirOptions, TouchOptions},
+};
+#[cfg(target_os = "linux")]
+use std::{ffi::OsString, fs::read_to_string, os::unix::ffi::OsStringExt};
 use std::{
-    fs::{create_dir, File},
-    io::Result,
+    io::{self, ErrorKind},
     path::{Path, PathBuf},
+    process,
 };
 
-pub enum CreateType {
-    Dir,
-    File,
+pub enum CreateType<'a> {
+    Dir(&'a MkdirOptions),
+    File(&'a TouchOptions),
 }
 
-fn get_vec_of_nonexistent_dirs(path: &PathBuf) -> Vec<PathBuf> {
-    let mut str_path = String::from(path.to_str().unwrap());
-    let mut missing_dirs: Vec<PathBuf> = vec![];
+/// Collects the paths passed to a function.
+pub fn paths(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect()
+}
 
-    while !Path::new(&str_path).parent().unwrap().exists() {
-        str_path = str_path.replace(
-            Path::new(&str_path).file_name().unwrap().to_str().unwrap(),
-            "",
-        );
-        missing_dirs.push(Path::new(&str_path.as_str()).to_path_buf());
+/// Creates the paths passed to [`mkdir_with`](crate::mkdir_with) or
+/// [`touch_with`](crate::touch_with), or plans creating them.
+pub fn create(exec: &dyn Exec, paths: &[PathBuf], create_type: &CreateType) -> Result<()> {
+    match create_type {
+        CreateType::Dir(options) => {
+            if paths.is_empty() {
+                err!(EmptyInput, "mkdir")
+            }
+            batch::for_each("mkdir", paths, options.on_error, |path| {
+                make_dir(exec, path, options)
+            })
+        }
+        CreateType::File(options) => {
+            if paths.is_empty() {
+                err!(EmptyInput, "touch")
+            }
+            batch::for_each("touch", paths, options.on_error, |path| {
+                make_file(exec, path, options)
+            })
+        }
     }
+}
 
-    missing_dirs.iter().rev().cloned().collect()
+fn make_dir(exec: &dyn Exec, path: &Path, options: &MkdirOptions) -> Result<()> {
+    if options.exist_ok && exec.is_dir(path) {
+        return Ok(());
+    }
+
+    if options.parents {
+        if let Some(parent) = path.parent() {
+            exec::create_dir_all(exec, "mkdir", parent)?;
+        }
+    }
+
+    exec.create_dir(path, options.mode).at("mkdir", path)
 }
 
-pub fn create(paths: &Vec<&PathBuf>, create_type: &CreateType) -> Result<()> {
-    if paths.len() == 1 {
-        match create_type {
-            CreateType::Dir => {
-                let missing_dirs: Vec<PathBuf> = get_vec_of_nonexistent_dirs(paths[0]);
-                if missing_dirs.len() > 0 {
-                    for dir in missing_dirs {
-                        create_dir(&dir)?;
-                    }
-                }
-                Ok(create_dir(paths[0])?)
+fn make_file(exec: &dyn Exec, path: &Path, options: &TouchOptions) -> Result<()> {
+    if options.parents {
+        if let Some(parent) = path.parent() {
+            exec::create_dir_all(exec, "touch", parent)?;
+        }
+    }
+
+    exec.create_file(path, options.truncate).at("touch", path)
+}
+
+/// Returns the first free path of the form `name (N).ext` next to `path`,
+/// starting from `N = 2`. `taken` tells if a path is already used.
+pub fn free_name_by<F>(path: &Path, taken: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !taken(p))
+        .unwrap()
+}
+
+/// Creates an entry with a free hidden name next to `path`, calling `create`
+/// with the candidate names until it doesn't fail with
+/// [`ErrorKind::AlreadyExists`]. New content is put there first and renamed
+/// over `path` once it's complete.
+pub fn create_temp<T, F>(path: &Path, create: F) -> io::Result<(PathBuf, T)>
+where
+    F: Fn(&Path) -> io::Result<T>,
+{
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    for n in 0.. {
+        let temp = path.with_file_name(format!(".{}.rfm-{}-{}", name, process::id(), n));
+        match create(&temp) {
+            Ok(created) => return Ok((temp, created)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Returns the mount points listed in `/proc/self/mounts`.
+#[cfg(target_os = "linux")]
+pub fn mount_points() -> Vec<PathBuf> {
+    // Mount points are listed with spaces and other special characters
+    // escaped as octal numbers.
+    let mounts = read_to_string("/proc/self/mounts").unwrap_or_default();
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|point| PathBuf::from(OsString::from_vec(unescape_mount(point))))
+        .collect()
+}
+
+/// Decodes the octal escapes (`\040` for a space) of `/proc/self/mounts`.
+#[cfg(target_os = "linux")]
+fn unescape_mount(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|oct| std::str::from_utf8(oct).ok())
+            .and_then(|oct| u8::from_str_radix(oct, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(b)) => {
+                unescaped.push(b);
+                i += 4;
             }
-            CreateType::File => {
-                File::create(&paths[0])?;
-                Ok(())
+            (b, _) => {
+                unescaped.push(b);
+                i += 1;
             }
         }
-    } else {
-        for path in paths {
-            let vec_of_path: Vec<&PathBuf> = vec![path];
-            create(&vec_of_path, &create_type).ok();
-        }
-        Ok(())
     }
+
+    unescaped
 }
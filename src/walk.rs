@@ -0,0 +1,384 @@
+use crate::{
+    error::{Context, Error, Result},
+    filter::Filter,
+};
+use std::{
+    fs::{metadata, read_dir, symlink_metadata, DirEntry, FileType, Metadata},
+    io, iter,
+    path::{Path, PathBuf},
+};
+
+type DirIter = Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>;
+
+/// A file, directory or symlink found by [`walk`](crate::walk).
+#[derive(Debug, Clone)]
+pub struct Entry {
+    path: PathBuf,
+    depth: usize,
+    file_type: FileType,
+    metadata: Metadata,
+    followed: bool,
+}
+
+impl Entry {
+    /// Creates an entry, following the symlink if `follow_links` is set.
+    /// A broken symlink is returned as it is.
+    fn new(path: PathBuf, depth: usize, link_meta: Metadata, follow_links: bool) -> Entry {
+        let (metadata, followed) = match link_meta.file_type().is_symlink() && follow_links {
+            true => match metadata(&path) {
+                Ok(target_meta) => (target_meta, true),
+                Err(_) => (link_meta, false),
+            },
+            false => (link_meta, false),
+        };
+
+        Entry {
+            path,
+            depth,
+            file_type: metadata.file_type(),
+            metadata,
+            followed,
+        }
+    }
+
+    /// The full path of the entry (the root joined with the relative path).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Converts the entry into its path.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Depth of the entry relative to the root, the root itself has depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Type of the entry. Symlinks are followed only if
+    /// [`follow_links`](Walk::follow_links) is enabled.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Metadata of the entry. Symlinks are followed only if
+    /// [`follow_links`](Walk::follow_links) is enabled.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns `true` if the path of the entry is a symlink, even if it was
+    /// followed.
+    pub fn path_is_symlink(&self) -> bool {
+        self.followed || self.is_symlink()
+    }
+
+    /// Returns `true` if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    /// Returns `true` if the entry is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    /// Returns `true` if the entry is a symlink that wasn't followed.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+}
+
+struct Dir {
+    path: PathBuf,
+    id: Option<FileId>,
+    entries: DirIter,
+    // Depth of the directory's children.
+    depth: usize,
+    // The directory itself, kept to be yielded after its contents.
+    entry: Option<Entry>,
+}
+
+struct Pending {
+    path: PathBuf,
+    id: Option<FileId>,
+    depth: usize,
+    entry: Option<Entry>,
+}
+
+/// Device and inode of a file, used to detect symlink loops and copies of a
+/// file onto itself.
+pub(crate) type FileId = (u64, u64);
+
+#[cfg(unix)]
+pub(crate) fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+/// Returns `true` if both metadata belong to the same file. Always `false`
+/// where file ids are not available.
+pub(crate) fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    match (file_id(a), file_id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// A lazy recursive iterator over a directory tree, created by
+/// [`walk`](crate::walk).
+///
+/// Directories are read only when the iterator reaches them, so only the
+/// currently open directories are held in memory.
+pub struct Walk {
+    op: &'static str,
+    root_path: PathBuf,
+    root: Option<PathBuf>,
+    filter: Filter,
+    min_depth: usize,
+    max_depth: usize,
+    contents_first: bool,
+    sort_by_name: bool,
+    follow_links: bool,
+    follow_root: bool,
+    // Depth of the root and the directories above it, if the root was
+    // found by another walk.
+    root_depth: usize,
+    ancestors: Vec<FileId>,
+    stack: Vec<Dir>,
+    pending: Option<Pending>,
+}
+
+impl Walk {
+    pub(crate) fn new(root: &Path) -> Walk {
+        Walk {
+            op: "walk",
+            root_path: root.to_path_buf(),
+            root: Some(root.to_path_buf()),
+            filter: Filter::new(),
+            min_depth: 0,
+            max_depth: usize::MAX,
+            contents_first: false,
+            sort_by_name: false,
+            follow_links: false,
+            follow_root: false,
+            root_depth: 0,
+            ancestors: vec![],
+            stack: vec![],
+            pending: None,
+        }
+    }
+
+    /// Name of the operation reported in errors.
+    pub(crate) fn op(mut self, op: &'static str) -> Walk {
+        self.op = op;
+        self
+    }
+
+    /// Sets the path that filters match the relative paths against, the root
+    /// by default.
+    pub(crate) fn relative_to(mut self, path: &Path) -> Walk {
+        self.root_path = path.to_path_buf();
+        self
+    }
+
+    /// Yields only the content of the root, which is a directory found by
+    /// another walk at `depth` below the directories with the `ancestors`
+    /// ids. The entries get their depth in the other walk, and symlink loops
+    /// are detected across both walks. Used to walk subtrees in parallel.
+    pub(crate) fn children_of(mut self, depth: usize, ancestors: Vec<FileId>) -> Walk {
+        self.root_depth = depth;
+        self.ancestors = ancestors;
+        self.min_depth = depth + 1;
+        self.max_depth = depth + 1;
+        self
+    }
+
+    /// Entries with a depth less than `depth` are not yielded (but are still
+    /// descended into). `0` by default, which means the root is yielded too.
+    pub fn min_depth(mut self, depth: usize) -> Walk {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Directories at `depth` are yielded, but not descended into.
+    /// Unlimited by default.
+    pub fn max_depth(mut self, depth: usize) -> Walk {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Yields the contents of a directory before the directory itself
+    /// (post-order). Useful for deletion. `false` by default.
+    pub fn contents_first(mut self, yes: bool) -> Walk {
+        self.contents_first = yes;
+        self
+    }
+
+    /// Yields the entries of every directory sorted by file name, instead of
+    /// the order returned by the OS. `false` by default.
+    pub fn sort_by_name(mut self, yes: bool) -> Walk {
+        self.sort_by_name = yes;
+        self
+    }
+
+    /// Follows symlinks: a symlink is returned with the type and metadata of
+    /// its target, and a symlink to a directory is descended into. A symlink
+    /// leading to a directory that is already being walked returns
+    /// [`Error::SymlinkLoop`] instead. `false` by default.
+    pub fn follow_links(mut self, yes: bool) -> Walk {
+        self.follow_links = yes;
+        self
+    }
+
+    /// Follows the root if it's a symlink, even if other symlinks are not
+    /// followed. Used by operations that take the root as a directory.
+    pub(crate) fn follow_root(mut self, yes: bool) -> Walk {
+        self.follow_root = yes;
+        self
+    }
+
+    /// Skips entries rejected by the filter. Skipped directories are not
+    /// descended into. A root directory is never skipped.
+    pub fn filter_entries(mut self, filter: Filter) -> Walk {
+        self.filter = filter;
+        self
+    }
+
+    /// Skips descending into the directory yielded last. Has no effect if the
+    /// last entry wasn't a directory or [`contents_first`](Walk::contents_first)
+    /// is enabled.
+    pub fn skip_current_dir(&mut self) {
+        self.pending = None;
+    }
+
+    fn error(&self, err: io::Error, path: &Path) -> Error {
+        Error::from_io(self.op, err, Some(path), None)
+    }
+
+    fn open(&self, path: &Path) -> DirIter {
+        match read_dir(path) {
+            Ok(rd) if self.sort_by_name => {
+                let mut entries: Vec<io::Result<DirEntry>> = rd.collect();
+                entries.sort_by_key(|e| e.as_ref().ok().map(DirEntry::file_name));
+                Box::new(entries.into_iter())
+            }
+            Ok(rd) => Box::new(rd),
+            Err(err) => Box::new(iter::once(Err(err))),
+        }
+    }
+
+    /// Returns the entry if it has to be yielded right away.
+    fn handle(&mut self, entry: Entry) -> Option<Result<Entry>> {
+        let is_root_dir = entry.depth == self.root_depth && entry.is_dir();
+        if !is_root_dir && self.filter.skips(&self.root_path, &entry) {
+            return None;
+        }
+
+        if entry.is_dir() && entry.depth < self.max_depth {
+            let path = entry.path.clone();
+            let id = file_id(&entry.metadata);
+            let depth = entry.depth;
+
+            let is_open = |id| {
+                self.stack.iter().any(|dir| dir.id == Some(id)) || self.ancestors.contains(&id)
+            };
+            if entry.followed && id.is_some_and(is_open) {
+                let ctx = Context::new(self.op, Some(&path), None);
+                return Some(Err(Error::SymlinkLoop(ctx)));
+            }
+
+            if self.contents_first {
+                self.pending = Some(Pending {
+                    path,
+                    id,
+                    depth,
+                    entry: Some(entry),
+                });
+                return None;
+            }
+
+            self.pending = Some(Pending {
+                path,
+                id,
+                depth,
+                entry: None,
+            });
+        }
+
+        if entry.depth >= self.min_depth {
+            Some(Ok(entry))
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if let Some(root) = self.root.take() {
+                match symlink_metadata(&root) {
+                    Ok(metadata) => {
+                        let follow = self.follow_links || self.follow_root;
+                        let entry = Entry::new(root, self.root_depth, metadata, follow);
+                        if let Some(entry) = self.handle(entry) {
+                            return Some(entry);
+                        }
+                    }
+                    Err(err) => return Some(Err(self.error(err, &root))),
+                }
+                continue;
+            }
+
+            if let Some(pending) = self.pending.take() {
+                let entries = self.open(&pending.path);
+                self.stack.push(Dir {
+                    path: pending.path,
+                    id: pending.id,
+                    entries,
+                    depth: pending.depth + 1,
+                    entry: pending.entry,
+                });
+            }
+
+            let dir = self.stack.last_mut()?;
+            let depth = dir.depth;
+
+            match dir.entries.next() {
+                None => {
+                    let dir = self.stack.pop()?;
+                    if let Some(entry) = dir.entry {
+                        if entry.depth >= self.min_depth {
+                            return Some(Ok(entry));
+                        }
+                    }
+                }
+                Some(Err(err)) => {
+                    let path = dir.path.clone();
+                    return Some(Err(self.error(err, &path)));
+                }
+                Some(Ok(dir_entry)) => {
+                    let path = dir_entry.path();
+                    match dir_entry.metadata() {
+                        Ok(metadata) => {
+                            let entry = Entry::new(path, depth, metadata, self.follow_links);
+                            if let Some(entry) = self.handle(entry) {
+                                return Some(entry);
+                            }
+                        }
+                        Err(err) => return Some(Err(self.error(err, &path))),
+                    }
+                }
+            }
+        }
+    }
+}
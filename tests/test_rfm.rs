@@ -1,7 +1,13 @@
 extern crate rfm;
 use std::{
-    fs::{create_dir, remove_dir_all, remove_file, File},
-    path::PathBuf,
+    fs::{
+        create_dir, create_dir_all, metadata, read_to_string, remove_dir_all, remove_file, write,
+        File, FileTimes,
+    },
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 
 static FILE_1: &str = "file-1.txt";
@@ -20,6 +26,45 @@ static MV: &str = "./tests/testing/mv";
 static RM: &str = "./tests/testing/rm";
 static TOUCH: &str = "./tests/testing/touch";
 static EXTRACT: &str = "./tests/testing/extract";
+static ERROR: &str = "./tests/testing/error";
+static FILTER: &str = "./tests/testing/filter";
+static CONFLICT: &str = "./tests/testing/conflict";
+static PRESERVE: &str = "./tests/testing/preserve";
+static OVERWRITE: &str = "./tests/testing/overwrite";
+static SYMLINK: &str = "./tests/testing/symlink";
+static SYMLINKED_ROOT: &str = "./tests/testing/symlinked-root";
+static MV_STRATEGY: &str = "./tests/testing/mv-strategy";
+static MV_ROLLBACK: &str = "./tests/testing/mv-rollback";
+static MV_NESTED: &str = "./tests/testing/mv-nested";
+static PROGRESS: &str = "./tests/testing/progress";
+static CANCEL: &str = "./tests/testing/cancel";
+static PLAN: &str = "./tests/testing/plan";
+static GUARD: &str = "./tests/testing/guard";
+static CLEAN_WITH: &str = "./tests/testing/clean-with";
+static PRUNE: &str = "./tests/testing/prune";
+static NAMING: &str = "./tests/testing/naming";
+static EXTRACT_MOVE: &str = "./tests/testing/extract-move";
+static USAGE: &str = "./tests/testing/usage";
+static DU: &str = "./tests/testing/du";
+static PARALLEL: &str = "./tests/testing/parallel";
+static PATHS: &str = "./tests/testing/paths";
+static MKDIR_WITH: &str = "./tests/testing/mkdir-with";
+static TOUCH_WITH: &str = "./tests/testing/touch-with";
+static BATCH: &str = "./tests/testing/batch";
+static TARGET: &str = "./tests/testing/target";
+static REFLINK: &str = "./tests/testing/reflink";
+
+/// Sets the access and modification times of `path` to `secs` seconds ago.
+fn backdate(path: &Path, secs: u64) {
+    let time = SystemTime::now() - Duration::from_secs(secs);
+    let times = FileTimes::new().set_accessed(time).set_modified(time);
+    File::options()
+        .read(true)
+        .open(path)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+}
 
 #[test]
 fn test_ls() {
@@ -46,32 +91,28 @@ fn test_ls() {
 #[test]
 fn test_cp() {
     let data_dir = PathBuf::from(&DATA);
-    let file_1 = data_dir.join(&FILE_1);
-    let file_2 = data_dir.join(&FILE_2);
+    let file_1 = data_dir.join(FILE_1);
+    let file_2 = data_dir.join(FILE_2);
     let files = vec![&file_1, &file_2];
-    let dir_1 = data_dir.join(&DIR_1);
-    let dir_2 = data_dir.join(&DIR_2);
+    let dir_1 = data_dir.join(DIR_1);
+    let dir_2 = data_dir.join(DIR_2);
     let dirs = vec![&dir_1, &dir_2];
 
     let to_path = PathBuf::from(&CP);
-    let expected_files: Vec<PathBuf> = vec![to_path.join(&FILE_1), to_path.join(&FILE_2)];
-    let expected_dirs: Vec<PathBuf> = vec![to_path.join(&DIR_1), to_path.join(&DIR_2)];
+    let expected_files: Vec<PathBuf> = vec![to_path.join(FILE_1), to_path.join(FILE_2)];
+    let expected_dirs: Vec<PathBuf> = vec![to_path.join(DIR_1), to_path.join(DIR_2)];
     let expected_content_of_dirs: Vec<PathBuf> = vec![
-        to_path.join(&DIR_1).join(&FILE_1),
-        to_path.join(&DIR_1).join(&FILE_2),
-        to_path.join(&DIR_2).join(&DIR_FOO),
-        to_path.join(&DIR_2).join(&FILE_1),
-        to_path.join(&DIR_2).join(&DIR_FOO).join(&DIR_BAR),
-        to_path.join(&DIR_2).join(&DIR_FOO).join(&FILE_1),
-        to_path
-            .join(&DIR_2)
-            .join(&DIR_FOO)
-            .join(&DIR_BAR)
-            .join(&FILE_1),
+        to_path.join(DIR_1).join(FILE_1),
+        to_path.join(DIR_1).join(FILE_2),
+        to_path.join(DIR_2).join(DIR_FOO),
+        to_path.join(DIR_2).join(FILE_1),
+        to_path.join(DIR_2).join(DIR_FOO).join(DIR_BAR),
+        to_path.join(DIR_2).join(DIR_FOO).join(FILE_1),
+        to_path.join(DIR_2).join(DIR_FOO).join(DIR_BAR).join(FILE_1),
     ];
 
-    remove_dir_all(&CP).ok();
-    create_dir(&CP).ok();
+    remove_dir_all(CP).ok();
+    create_dir(CP).ok();
 
     rfm::cp(&files, &to_path).ok();
     rfm::cp(&dirs, &to_path).ok();
@@ -108,13 +149,27 @@ fn test_cp() {
         expected_content_of_dirs[6].exists(),
         "Copied dirs content should exist"
     );
+
+    let dir = to_path.join(DIR_1);
+    let inner = dir.join(DIR_FOO);
+    create_dir(&inner).ok();
+    let err = rfm::cp(vec![&dir], &inner).unwrap_err();
+    assert!(
+        matches!(err, rfm::Error::DestinationExists(_)),
+        "Directory should not be copied into itself"
+    );
+    assert_eq!(
+        std::fs::read_dir(&inner).unwrap().count(),
+        0,
+        "Nothing should be copied into the directory"
+    );
 }
 
 #[test]
 fn test_rm() {
     let rm_dir = PathBuf::from(&RM);
-    let dir = rm_dir.join(&DIR_2);
-    let file = rm_dir.join(&FILE_1);
+    let dir = rm_dir.join(DIR_2);
+    let file = rm_dir.join(FILE_1);
 
     let elements = vec![&dir, &file];
 
@@ -134,8 +189,8 @@ fn test_rm() {
 #[test]
 fn test_mkdir() {
     let mkdir_dir = PathBuf::from(&MKDIR);
-    let dir_foo = mkdir_dir.join(&DIR_FOO);
-    let few_dirs = mkdir_dir.join(&DIR_2).join(&DIR_FOO).join(&DIR_BAR);
+    let dir_foo = mkdir_dir.join(DIR_FOO);
+    let few_dirs = mkdir_dir.join(DIR_2).join(DIR_FOO).join(DIR_BAR);
     let dirs = vec![&dir_foo, &few_dirs];
 
     if few_dirs.exists() {
@@ -154,8 +209,8 @@ fn test_mkdir() {
 #[test]
 fn test_touch() {
     let touch_dir = PathBuf::from(&TOUCH);
-    let file_1 = touch_dir.join(&FILE_1);
-    let file_2 = touch_dir.join(&FILE_2);
+    let file_1 = touch_dir.join(FILE_1);
+    let file_2 = touch_dir.join(FILE_2);
     let files = vec![&file_1, &file_2];
 
     if !touch_dir.exists() {
@@ -178,9 +233,9 @@ fn test_touch() {
 #[test]
 fn test_clean() {
     let clean_dir = PathBuf::from(&CLEAN);
-    let file_1 = clean_dir.join(&FILE_1);
-    let file_2 = clean_dir.join(&FILE_2);
-    let dir_1 = clean_dir.join(&DIR_1);
+    let file_1 = clean_dir.join(FILE_1);
+    let file_2 = clean_dir.join(FILE_2);
+    let dir_1 = clean_dir.join(DIR_1);
 
     if !clean_dir.exists() {
         create_dir(&clean_dir).ok();
@@ -190,7 +245,7 @@ fn test_clean() {
     File::create(&file_2).ok();
     create_dir(&dir_1).ok();
 
-    rfm::clean(&vec![&clean_dir]).ok();
+    rfm::clean(vec![&clean_dir]).ok();
     assert!(!file_1.exists(), "Cleaned dir should not exist any files");
     assert!(!file_2.exists(), "Cleaned dir should not exist any files");
     assert!(!dir_1.exists(), "Cleaned dir should not exist any dirs");
@@ -199,12 +254,12 @@ fn test_clean() {
 #[test]
 fn test_mv() {
     let mv_dir = PathBuf::from(&MV);
-    let dir_1 = mv_dir.join(&DIR_1);
-    let dir_2 = mv_dir.join(&DIR_2);
-    let file_1 = mv_dir.join(&DIR_2).join(&FILE_1);
+    let dir_1 = mv_dir.join(DIR_1);
+    let dir_2 = mv_dir.join(DIR_2);
+    let file_1 = mv_dir.join(DIR_2).join(FILE_1);
 
-    let expected_dir = dir_1.join(&DIR_2);
-    let expected_file = dir_1.join(&DIR_2).join(&FILE_1);
+    let expected_dir = dir_1.join(DIR_2);
+    let expected_file = dir_1.join(DIR_2).join(FILE_1);
 
     let need_to_mv = vec![&dir_2];
 
@@ -240,7 +295,7 @@ fn test_mv() {
 fn test_extract() {
     let data_dir = PathBuf::from(&DATA);
     let extract_dir = PathBuf::from(&EXTRACT);
-    let dir_3 = data_dir.join(&DIR_3);
+    let dir_3 = data_dir.join(DIR_3);
     let need_to_extract = vec![&dir_3];
 
     let expected_file_1 = extract_dir.join("file-1.txt");
@@ -282,3 +337,1864 @@ fn test_get_size() {
 
     assert!(file_size == 6, "Size of test file should be 6 bytes");
 }
+
+#[test]
+fn test_error() {
+    let error_dir = PathBuf::from(ERROR);
+    let missing = error_dir.join(FILE_1);
+    let file_2 = PathBuf::from(DATA).join(FILE_2);
+    let missing_to = error_dir.join(DIR_1);
+
+    remove_dir_all(&error_dir).ok();
+    create_dir(&error_dir).ok();
+
+    let error = rfm::rm(vec![&missing]).unwrap_err();
+    assert!(
+        matches!(error, rfm::Error::NotFound(_)),
+        "Deleting missing file should return NotFound"
+    );
+    assert_eq!(error.op(), "rm", "Error should contain operation name");
+    assert_eq!(
+        error.from_path(),
+        Some(missing.as_path()),
+        "Error should contain failing path"
+    );
+
+    let error = rfm::cp(vec![&file_2], &missing_to).unwrap_err();
+    assert_eq!(
+        error.to_path(),
+        Some(missing_to.join(FILE_2).as_path()),
+        "Error should contain destination path"
+    );
+
+    let empty: Vec<&PathBuf> = vec![];
+    assert!(
+        matches!(rfm::mv(&empty, &error_dir), Err(rfm::Error::EmptyInput(_))),
+        "Empty input should return EmptyInput"
+    );
+
+    let io_error: io::Error = error.into();
+    assert_eq!(
+        io_error.kind(),
+        io::ErrorKind::NotFound,
+        "Conversion to io::Error should keep the kind"
+    );
+
+    let error: rfm::Error = io_error.into();
+    assert_eq!(error.op(), "cp", "Round trip should keep the context");
+}
+
+#[test]
+fn test_walk() {
+    let dir_3 = PathBuf::from(DATA).join(DIR_3);
+    let subdir_1 = dir_3.join("subdir-1");
+
+    let all: Vec<rfm::Entry> = rfm::walk(&dir_3).map(|e| e.unwrap()).collect();
+    assert_eq!(
+        all.len(),
+        7,
+        "Walk should return root and all nested entries"
+    );
+    assert_eq!(all[0].path(), dir_3, "Walk should return root first");
+    assert_eq!(all[0].depth(), 0, "Root should have depth 0");
+
+    let max_depth_1 = rfm::walk(&dir_3).max_depth(1).count();
+    assert_eq!(max_depth_1, 3, "Walk shouldn't go deeper than max depth");
+
+    assert!(
+        rfm::walk(&dir_3)
+            .min_depth(2)
+            .all(|e| e.unwrap().depth() >= 2),
+        "Walk shouldn't return entries above min depth"
+    );
+
+    let contents_first: Vec<PathBuf> = rfm::walk(&dir_3)
+        .contents_first(true)
+        .map(|e| e.unwrap().into_path())
+        .collect();
+    let position = |p: &PathBuf| contents_first.iter().position(|i| i == p).unwrap();
+    assert_eq!(
+        contents_first.last(),
+        Some(&dir_3),
+        "Contents first walk should return root last"
+    );
+    assert!(
+        position(&subdir_1.join(FILE_2)) < position(&subdir_1),
+        "Contents first walk should return dir after its content"
+    );
+
+    let mut entries = rfm::walk(&dir_3);
+    let mut count = 0;
+    while let Some(entry) = entries.next() {
+        if entry.unwrap().path() == subdir_1 {
+            entries.skip_current_dir();
+        }
+        count += 1;
+    }
+    assert_eq!(count, 3, "Skipped dir content shouldn't be returned");
+}
+
+#[test]
+fn test_filter() {
+    let data_dir = PathBuf::from(DATA);
+    let dir_2 = data_dir.join(DIR_2);
+    let dir_3 = data_dir.join(DIR_3);
+    let filter_dir = PathBuf::from(FILTER);
+    let cp_dir = filter_dir.join("cp");
+    let extract_dir = filter_dir.join("extract");
+    let rm_dir = filter_dir.join("rm");
+
+    remove_dir_all(&filter_dir).ok();
+    for dir in [&filter_dir, &cp_dir, &extract_dir, &rm_dir].iter() {
+        create_dir(dir).ok();
+    }
+
+    let options = rfm::LsOptions::new().filter(rfm::Filter::new().include("file-*"));
+    let content = rfm::ls_with(&data_dir, &options).unwrap();
+    assert!(
+        content
+            .iter()
+            .all(|p| p.is_dir() || p.ends_with(FILE_1) || p.ends_with(FILE_2)),
+        "ls should return only included files"
+    );
+
+    let options = rfm::CopyOptions::new().filter(rfm::Filter::new().exclude("foo/"));
+    rfm::cp_with(vec![&dir_2], &cp_dir, &options).unwrap();
+    assert!(
+        cp_dir.join(DIR_2).join(FILE_1).exists(),
+        "Not excluded file should be copied"
+    );
+    assert!(
+        !cp_dir.join(DIR_2).join(DIR_FOO).exists(),
+        "Excluded dir should not be copied"
+    );
+
+    let options =
+        rfm::ExtractOptions::new().filter(rfm::Filter::new().include("**/subdir-2/file-[3-9].txt"));
+    rfm::extract_with(vec![&dir_3], &extract_dir, &options).unwrap();
+    assert_eq!(
+        rfm::ls(&extract_dir).unwrap().len(),
+        2,
+        "Only included files should be extracted"
+    );
+
+    let options = rfm::SizeOptions::new().filter(rfm::Filter::new().exclude("subdir-1"));
+    let size = rfm::get_size_with(&dir_3, &options).unwrap();
+    assert_eq!(
+        size,
+        rfm::get_size(dir_3.join(FILE_1)).unwrap(),
+        "Size of excluded dir should not be counted"
+    );
+
+    let tmp_file = rm_dir.join("file.tmp");
+    let txt_file = rm_dir.join(FILE_1);
+    File::create(&tmp_file).ok();
+    File::create(&txt_file).ok();
+
+    let options = rfm::RemoveOptions::new().filter(
+        rfm::Filter::new()
+            .predicate(|entry| entry.path().extension().is_none_or(|ext| ext == "tmp")),
+    );
+    rfm::rm_with(vec![&rm_dir], &options).unwrap();
+    assert!(!tmp_file.exists(), "Accepted file should be deleted");
+    assert!(txt_file.exists(), "Rejected file should not be deleted");
+    assert!(rm_dir.exists(), "Not empty dir should not be deleted");
+
+    let include_dir = filter_dir.join("rm-include");
+    let options = rfm::RemoveOptions::new().filter(rfm::Filter::new().include("*.tmp"));
+    for threads in [1, 2] {
+        remove_dir_all(&include_dir).ok();
+        for dir in ["", "empty", "emptied", "cache.tmp"] {
+            create_dir(include_dir.join(dir)).ok();
+        }
+        File::create(include_dir.join("a.tmp")).ok();
+        File::create(include_dir.join("emptied").join("b.tmp")).ok();
+
+        rfm::rm_with([&include_dir], &options.clone().threads(threads)).unwrap();
+        assert!(!include_dir.join("a.tmp").exists());
+        assert!(
+            !include_dir.join("emptied").exists(),
+            "Emptied dir should be deleted"
+        );
+        assert!(
+            !include_dir.join("cache.tmp").exists(),
+            "Matching dir should be deleted"
+        );
+        assert!(
+            include_dir.join("empty").exists(),
+            "Unmatched empty dir should stay"
+        );
+        assert!(include_dir.exists(), "Not empty root should stay");
+
+        // The root is emptied by the call once the unmatched dir is gone.
+        remove_dir_all(include_dir.join("empty")).ok();
+        File::create(include_dir.join("c.tmp")).ok();
+        rfm::rm_with([&include_dir], &options).unwrap();
+        assert!(!include_dir.exists(), "Emptied root should be deleted");
+    }
+}
+
+#[test]
+fn test_conflict() {
+    let data_dir = PathBuf::from(DATA);
+    let dir_1 = data_dir.join(DIR_1);
+    let conflict_dir = PathBuf::from(CONFLICT);
+    let to = conflict_dir.join("to");
+    let existing = to.join(DIR_1).join(FILE_1);
+    let mv_from = conflict_dir.join("from");
+    let mv_file_1 = mv_from.join(FILE_1);
+    let mv_file_2 = mv_from.join(FILE_2);
+
+    remove_dir_all(&conflict_dir).ok();
+    create_dir(&conflict_dir).ok();
+    create_dir(&to).ok();
+
+    rfm::cp(vec![&dir_1], &to).unwrap();
+    rfm::cp(vec![&dir_1], &to).expect("Existing dir should be merged by default");
+
+    write(&existing, "local").ok();
+    let options = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::Skip);
+    rfm::cp_with(vec![&dir_1], &to, &options).unwrap();
+    assert_eq!(
+        read_to_string(&existing).unwrap(),
+        "local",
+        "Skipped file should not be overwritten"
+    );
+
+    let options = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::Error);
+    assert!(
+        matches!(
+            rfm::cp_with(vec![&dir_1], &to, &options),
+            Err(rfm::Error::DestinationExists(_))
+        ),
+        "Existing dir should return an error"
+    );
+
+    let options = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::OverwriteIfDifferent);
+    rfm::cp_with(vec![&dir_1], &to, &options).unwrap();
+    assert_eq!(
+        read_to_string(&existing).unwrap(),
+        read_to_string(dir_1.join(FILE_1)).unwrap(),
+        "Different file should be overwritten"
+    );
+
+    let options = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::RenameWithSuffix);
+    rfm::cp_with(vec![&dir_1], &to, &options).unwrap();
+    assert!(
+        to.join("dir-1 (2)").join(FILE_1).exists(),
+        "Renamed dir should be created next to existing one"
+    );
+
+    let options = rfm::ExtractOptions::new().conflict(rfm::ConflictPolicy::custom(|_, to| {
+        if to.ends_with(FILE_1) {
+            rfm::ConflictAction::Rename
+        } else {
+            rfm::ConflictAction::Skip
+        }
+    }));
+    rfm::extract_with(vec![&dir_1], &to, &options).unwrap();
+    rfm::extract_with(vec![&dir_1], &to, &options).unwrap();
+    assert!(
+        to.join("file-1 (2).txt").exists(),
+        "Callback should decide what to do with a conflict"
+    );
+    assert!(
+        !to.join("file-2 (2).txt").exists(),
+        "Callback should decide what to do with a conflict"
+    );
+
+    create_dir(&mv_from).ok();
+    create_dir(to.join("from")).ok();
+    File::create(&mv_file_1).ok();
+    File::create(&mv_file_2).ok();
+    File::create(to.join("from").join(FILE_1)).ok();
+    let options = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::Skip);
+    rfm::mv_with(vec![&mv_from], &to, &options).unwrap();
+    assert!(
+        mv_file_1.exists(),
+        "Skipped file should stay at the starting point"
+    );
+    assert!(
+        !mv_file_2.exists() && to.join("from").join(FILE_2).exists(),
+        "Not conflicting file should be moved into existing dir"
+    );
+
+    // Overwrite replaces only entries of the same kind, and only once the
+    // new entry is complete.
+    let overwrite = rfm::CopyOptions::new().conflict(rfm::ConflictPolicy::custom(|_, _| {
+        rfm::ConflictAction::Overwrite
+    }));
+    let file_1 = data_dir.join(FILE_1);
+    let kind_dir = conflict_dir.join("kind");
+    create_dir(&kind_dir).ok();
+    write(kind_dir.join(DIR_1), "kept").ok();
+    create_dir(kind_dir.join(FILE_1)).ok();
+    write(kind_dir.join(FILE_1).join(FILE_2), "kept").ok();
+    for path in [&dir_1, &file_1] {
+        assert!(matches!(
+            rfm::cp_with([path], &kind_dir, &overwrite),
+            Err(rfm::Error::DestinationExists(_))
+        ));
+        assert!(matches!(
+            rfm::mv_with([path], &kind_dir, &overwrite),
+            Err(rfm::Error::DestinationExists(_))
+        ));
+        assert!(path.exists(), "Source should stay");
+    }
+    let as_name = overwrite.clone().no_target_directory(true);
+    assert!(matches!(
+        rfm::cp_with([&file_1], kind_dir.join(FILE_1), &as_name),
+        Err(rfm::Error::DestinationExists(_))
+    ));
+    assert_eq!(read_to_string(kind_dir.join(DIR_1)).unwrap(), "kept");
+    assert_eq!(
+        read_to_string(kind_dir.join(FILE_1).join(FILE_2)).unwrap(),
+        "kept",
+        "Entries of another kind should not be overwritten"
+    );
+
+    let replace_dir = conflict_dir.join("replace");
+    let replaced = replace_dir.join(DIR_1);
+    let mv_dir = conflict_dir.join("mv").join(DIR_1);
+    let names = |path: &Path| -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    };
+    create_dir(&replace_dir).ok();
+    create_dir(&replaced).ok();
+    write(replaced.join("old.txt"), "old").ok();
+    rfm::cp_with([&dir_1], &replace_dir, &overwrite).unwrap();
+    assert_eq!(
+        names(&replace_dir),
+        [DIR_1],
+        "No temporary entries should be left"
+    );
+    assert_eq!(
+        names(&replaced),
+        [FILE_1, FILE_2],
+        "Old content should be gone"
+    );
+
+    write(replaced.join("old.txt"), "old").ok();
+    create_dir(conflict_dir.join("mv")).ok();
+    rfm::cp(vec![&dir_1], conflict_dir.join("mv")).ok();
+    rfm::mv_with([&mv_dir], &replace_dir, &overwrite).unwrap();
+    assert_eq!(
+        names(&replace_dir),
+        [DIR_1],
+        "No temporary entries should be left"
+    );
+    assert_eq!(
+        names(&replaced),
+        [FILE_1, FILE_2],
+        "Old content should be gone"
+    );
+    assert!(!mv_dir.exists());
+
+    // The plan performs the same swap.
+    write(replaced.join("old.txt"), "old").ok();
+    let plan = rfm::Plan::cp_with([&dir_1], &replace_dir, &overwrite).unwrap();
+    assert!(
+        replaced.join("old.txt").exists(),
+        "Planning should not change anything"
+    );
+    plan.execute().unwrap();
+    assert_eq!(
+        names(&replace_dir),
+        [DIR_1],
+        "No temporary entries should be left"
+    );
+    assert_eq!(
+        names(&replaced),
+        [FILE_1, FILE_2],
+        "Old content should be gone"
+    );
+
+    // Overwriting a file with itself would delete it.
+    #[cfg(unix)]
+    {
+        let link = conflict_dir.join(FILE_1);
+        std::fs::hard_link(&mv_file_1, &link).ok();
+        write(&link, "content").ok();
+        assert!(matches!(
+            rfm::cp([&mv_file_1], &conflict_dir),
+            Err(rfm::Error::DestinationExists(_))
+        ));
+        assert!(matches!(
+            rfm::mv([&mv_file_1], &conflict_dir),
+            Err(rfm::Error::DestinationExists(_))
+        ));
+        assert_eq!(read_to_string(&link).unwrap(), "content");
+        assert!(mv_file_1.exists(), "Source should stay");
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let name = CString::new(name).unwrap();
+    let res = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+    res == 0
+}
+
+#[cfg(target_os = "linux")]
+fn get_xattr(path: &Path, name: &str) -> Vec<u8> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let name = CString::new(name).unwrap();
+    let mut buf = vec![0u8; 64];
+    let len = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+        )
+    };
+    buf.truncate(len.max(0) as usize);
+    buf
+}
+
+#[test]
+fn test_preserve() {
+    let preserve_dir = PathBuf::from(PRESERVE);
+    let from = preserve_dir.join(DIR_1);
+    let file = from.join(FILE_1);
+    let to = preserve_dir.join("to");
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let times = FileTimes::new().set_accessed(time).set_modified(time);
+
+    remove_dir_all(&preserve_dir).ok();
+    create_dir(&preserve_dir).ok();
+    create_dir(&from).ok();
+    create_dir(&to).ok();
+    write(&file, "data").ok();
+
+    #[cfg(target_os = "linux")]
+    let has_xattrs = set_xattr(&file, "user.rfm", b"value");
+
+    #[cfg(unix)]
+    {
+        use std::{fs::set_permissions, os::unix::fs::PermissionsExt};
+        set_permissions(&file, PermissionsExt::from_mode(0o640)).unwrap();
+        set_permissions(&from, PermissionsExt::from_mode(0o750)).unwrap();
+    }
+
+    File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+    File::open(&from).unwrap().set_times(times).unwrap();
+
+    let options = rfm::CopyOptions::new().preserve(rfm::Preserve::all());
+    rfm::cp_with(vec![&from], &to, &options).unwrap();
+
+    for (path, copy) in [
+        (&from, to.join(DIR_1)),
+        (&file, to.join(DIR_1).join(FILE_1)),
+    ]
+    .iter()
+    {
+        let expected = metadata(path).unwrap();
+        let actual = metadata(copy).unwrap();
+
+        assert_eq!(
+            actual.modified().unwrap(),
+            expected.modified().unwrap(),
+            "Modification time should be preserved"
+        );
+        assert_eq!(
+            actual.permissions(),
+            expected.permissions(),
+            "Permissions should be preserved"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if has_xattrs {
+            assert_eq!(
+                get_xattr(&to.join(DIR_1).join(FILE_1), "user.rfm"),
+                b"value",
+                "Extended attributes should be preserved"
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_overwrite_replaces_destination() {
+    use std::os::unix::fs::{symlink, MetadataExt};
+
+    let dir = PathBuf::from(&OVERWRITE);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+    let other = dir.join(FILE_2);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&from).ok();
+    create_dir(&to).ok();
+    write(from.join(FILE_1), "new").ok();
+    write(from.join(FILE_2), "new").ok();
+    write(&other, "old").ok();
+    symlink(std::fs::canonicalize(&other).unwrap(), to.join(FILE_1)).ok();
+    std::fs::hard_link(&other, to.join(FILE_2)).ok();
+
+    rfm::cp([from.join(FILE_1), from.join(FILE_2)], &to).unwrap();
+    assert!(
+        !std::fs::symlink_metadata(to.join(FILE_1))
+            .unwrap()
+            .file_type()
+            .is_symlink(),
+        "Overwritten symlink should be replaced by the file"
+    );
+    assert_eq!(read_to_string(to.join(FILE_1)).unwrap(), "new");
+    assert_eq!(read_to_string(to.join(FILE_2)).unwrap(), "new");
+    assert_eq!(
+        read_to_string(&other).unwrap(),
+        "old",
+        "Symlink target and other hard links should keep the old content"
+    );
+    assert_eq!(metadata(&other).unwrap().nlink(), 1);
+
+    // Only root can give the destination another owner.
+    if std::os::unix::fs::chown(to.join(FILE_2), Some(1), None).is_ok() {
+        rfm::cp([from.join(FILE_2)], &to).unwrap();
+        assert_ne!(
+            metadata(to.join(FILE_2)).unwrap().uid(),
+            1,
+            "Owner of the overwritten file should not be kept"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    if set_xattr(&to.join(FILE_2), "user.rfm", b"old") {
+        rfm::cp([from.join(FILE_2)], &to).unwrap();
+        assert!(
+            get_xattr(&to.join(FILE_2), "user.rfm").is_empty(),
+            "Extended attributes of the overwritten file should not be kept"
+        );
+    }
+
+    // Cancels the copy over `file-1.txt` after its first chunk.
+    let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    write(from.join(FILE_1), &content).ok();
+    write(to.join(FILE_1), "old").ok();
+    let token = rfm::CancelToken::new();
+    let cancel = token.clone();
+    let progress = rfm::Progress::new(move |event| {
+        if let rfm::Event::BytesCopied(_) = event {
+            cancel.cancel();
+        }
+    });
+
+    let options = rfm::CopyOptions::new()
+        .reflink(rfm::Reflink::Never)
+        .progress(progress)
+        .cancel(token);
+    let error = rfm::cp_with([from.join(FILE_1)], &to, &options).unwrap_err();
+    assert!(matches!(error, rfm::Error::Cancelled(_)));
+    assert_eq!(
+        read_to_string(to.join(FILE_1)).unwrap(),
+        "old",
+        "Cancelled copy should keep the destination"
+    );
+    assert_eq!(
+        std::fs::read_dir(&to).unwrap().count(),
+        2,
+        "The temporary file should be deleted"
+    );
+}
+
+#[test]
+fn test_symlink() {
+    use std::{fs::symlink_metadata, os::unix::fs::symlink};
+
+    let symlink_dir = PathBuf::from(SYMLINK);
+    let from = symlink_dir.join(DIR_1);
+    let file_1 = from.join(FILE_1);
+    let link_to_file = from.join("link-to-file");
+    let link_to_dir = from.join("link-to-dir");
+    let to = symlink_dir.join("to");
+    let is_symlink = |p: &Path| symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink());
+
+    remove_dir_all(&symlink_dir).ok();
+    create_dir(&symlink_dir).ok();
+    create_dir(&from).ok();
+    create_dir(&to).ok();
+    write(&file_1, "data").ok();
+    symlink(FILE_1, &link_to_file).ok();
+    // Points to its own parent, following it leads to a loop.
+    symlink(".", &link_to_dir).ok();
+
+    rfm::cp(vec![&from], &to).unwrap();
+    assert!(
+        is_symlink(&to.join(DIR_1).join("link-to-file")),
+        "Symlink should be copied as symlink by default"
+    );
+    assert!(
+        is_symlink(&to.join(DIR_1).join("link-to-dir")),
+        "Symlink to dir should be copied as symlink by default"
+    );
+
+    let options = rfm::CopyOptions::new().symlinks(rfm::SymlinkPolicy::Follow);
+    create_dir(to.join("follow")).ok();
+    assert!(
+        matches!(
+            rfm::cp_with(vec![&from], to.join("follow"), &options),
+            Err(rfm::Error::SymlinkLoop(_))
+        ),
+        "Symlink loop should be detected"
+    );
+
+    let options = rfm::ExtractOptions::new().symlinks(rfm::SymlinkPolicy::Follow);
+    let extract_to = to.join("extract");
+    create_dir(&extract_to).ok();
+    assert!(
+        rfm::extract_with(vec![&from], &extract_to, &options).is_err(),
+        "Symlink loop should be detected"
+    );
+    assert!(
+        !is_symlink(&extract_to.join("link-to-file")),
+        "Followed symlink should be copied as file"
+    );
+
+    let options = rfm::CopyOptions::new()
+        .symlinks(rfm::SymlinkPolicy::Skip)
+        .conflict(rfm::ConflictPolicy::Error);
+    let skip_to = to.join("skip");
+    create_dir(&skip_to).ok();
+    rfm::cp_with(vec![&from], &skip_to, &options).unwrap();
+    assert!(
+        skip_to.join(DIR_1).join(FILE_1).exists()
+            && !is_symlink(&skip_to.join(DIR_1).join("link-to-file")),
+        "Skipped symlink should not be copied"
+    );
+
+    let options = rfm::CopyOptions::new().symlinks(rfm::SymlinkPolicy::Error);
+    create_dir(to.join("error")).ok();
+    assert!(
+        matches!(
+            rfm::cp_with(vec![&from], to.join("error"), &options),
+            Err(rfm::Error::Symlink(_))
+        ),
+        "Symlink should return an error"
+    );
+
+    rfm::rm(vec![&link_to_dir]).unwrap();
+    assert!(
+        !is_symlink(&link_to_dir) && file_1.exists(),
+        "rm should delete the symlink, not its target"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_root() {
+    let dir = PathBuf::from(SYMLINKED_ROOT);
+    let link = dir.join("link");
+    let to = dir.join("to");
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&to).ok();
+    rfm::cp([PathBuf::from(DATA).join(DIR_2)], &dir).ok();
+    std::os::unix::fs::symlink(DIR_2, &link).ok();
+
+    let mut listed = rfm::ls(&link).unwrap();
+    listed.sort();
+    assert_eq!(listed, [link.join(FILE_1), link.join(DIR_FOO)]);
+
+    let options = rfm::ExtractOptions::new()
+        .move_files(true)
+        .remove_root(true);
+    rfm::extract_with([&link], &to, &options).unwrap();
+    assert!(to.join(FILE_1).exists());
+    assert!(
+        !dir.join(DIR_2).join(DIR_FOO).exists(),
+        "Emptied dirs should be deleted"
+    );
+    assert!(
+        link.symlink_metadata().is_ok(),
+        "Symlinked root should be kept"
+    );
+
+    std::os::unix::fs::symlink("missing", dir.join("broken")).ok();
+    assert!(matches!(
+        rfm::ls(dir.join("broken")),
+        Err(rfm::Error::NotFound(_))
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_mv_strategy() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = PathBuf::from(&MV_STRATEGY);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&from).ok();
+    create_dir(&to).ok();
+    write(from.join(FILE_1), "data").ok();
+
+    let ino = metadata(from.join(FILE_1)).unwrap().ino();
+    let report = rfm::mv_with(vec![&from], &to, &rfm::CopyOptions::new()).unwrap();
+    assert_eq!(
+        report.moved().count(),
+        1,
+        "Directory should be moved as a whole"
+    );
+    assert_eq!(
+        report.moved().next().unwrap().strategy(),
+        rfm::MoveStrategy::Rename,
+        "Directory on the same filesystem should be renamed"
+    );
+    assert_eq!(
+        metadata(to.join(DIR_1).join(FILE_1)).unwrap().ino(),
+        ino,
+        "Renamed file should keep its inode"
+    );
+
+    // The fallback can only be checked if there is another filesystem.
+    let other_fs = Path::new("/dev/shm");
+    if !other_fs.is_dir() || metadata(other_fs).unwrap().dev() == metadata(&dir).unwrap().dev() {
+        return;
+    }
+
+    let other_dir = other_fs.join(format!("rfm-mv-strategy-{}", std::process::id()));
+    remove_dir_all(&other_dir).ok();
+    create_dir(&other_dir).ok();
+
+    let moved = to.join(DIR_1);
+    let report = rfm::mv_with(vec![&moved], &other_dir, &rfm::CopyOptions::new()).unwrap();
+    let copied = read_to_string(other_dir.join(DIR_1).join(FILE_1));
+    remove_dir_all(&other_dir).ok();
+
+    assert_eq!(
+        report.moved().next().unwrap().strategy(),
+        rfm::MoveStrategy::CopyAndDelete,
+        "Directory on another filesystem should be copied and deleted"
+    );
+    assert_eq!(
+        copied.unwrap(),
+        "data",
+        "Copied file should have the same content"
+    );
+    assert!(!moved.exists(), "Source should be deleted after the copy");
+}
+
+#[test]
+fn test_mv_rollback() {
+    let dir = PathBuf::from(&MV_ROLLBACK);
+    let from = dir.join("from");
+    let to = dir.join("to");
+    let dir_1 = from.join(DIR_1);
+    let dir_2 = from.join(DIR_2);
+    let missing = from.join(DIR_3);
+
+    let setup = || {
+        remove_dir_all(&dir).ok();
+        for d in [&dir, &from, &to, &dir_1, &dir_2, &to.join(DIR_2)] {
+            create_dir(d).ok();
+        }
+        write(dir_1.join(FILE_1), "1").ok();
+        write(dir_2.join(FILE_1), "1").ok();
+        write(dir_2.join(FILE_2), "2").ok();
+        write(to.join(DIR_2).join(FILE_2), "old").ok();
+    };
+
+    setup();
+    let options = rfm::CopyOptions::new();
+    let err = rfm::mv_with(vec![&dir_1, &missing, &dir_2], &to, &options).unwrap_err();
+    assert!(matches!(err, rfm::Error::NotFound(_)));
+    assert!(
+        to.join(DIR_1).join(FILE_1).exists(),
+        "Entries moved before the error should stay moved"
+    );
+    let report = err.report().expect("mv error should carry a report");
+    assert_eq!(report.status(&dir_1), Some(rfm::MoveStatus::Moved));
+    assert_eq!(report.status(&missing), Some(rfm::MoveStatus::Untouched));
+    assert_eq!(
+        report.status(&dir_2),
+        Some(rfm::MoveStatus::Untouched),
+        "Sources after the error should be reported"
+    );
+
+    setup();
+    let conflict = rfm::ConflictPolicy::custom(|from, _| match from.ends_with(FILE_2) {
+        true => rfm::ConflictAction::Error,
+        false => rfm::ConflictAction::Merge,
+    });
+    let options = rfm::CopyOptions::new().conflict(conflict).rollback(true);
+    let err = rfm::mv_with(vec![&dir_1, &dir_2], &to, &options).unwrap_err();
+    assert!(matches!(err, rfm::Error::DestinationExists(_)));
+    assert!(
+        dir_1.join(FILE_1).exists() && !to.join(DIR_1).exists(),
+        "Moved directory should be moved back"
+    );
+    assert!(
+        dir_2.join(FILE_1).exists() && dir_2.join(FILE_2).exists(),
+        "Merged content should be moved back"
+    );
+    assert_eq!(
+        read_to_string(to.join(DIR_2).join(FILE_2)).unwrap(),
+        "old",
+        "Existing destination should be left as it was"
+    );
+    let report = err.report().unwrap();
+    assert!(
+        report
+            .sources()
+            .all(|(_, status)| status == rfm::MoveStatus::Untouched),
+        "Everything should be rolled back"
+    );
+
+    setup();
+    let conflict = rfm::ConflictPolicy::custom(|from, _| match from.ends_with(FILE_2) {
+        true => rfm::ConflictAction::Overwrite,
+        false => rfm::ConflictAction::Merge,
+    });
+    let options = rfm::CopyOptions::new().conflict(conflict).rollback(true);
+    let err = rfm::mv_with(vec![&dir_2, &missing], &to, &options).unwrap_err();
+    assert!(matches!(err, rfm::Error::NotFound(_)));
+    assert_eq!(
+        read_to_string(to.join(DIR_2).join(FILE_2)).unwrap(),
+        "old",
+        "Overwritten file should be put back"
+    );
+    assert_eq!(read_to_string(dir_2.join(FILE_2)).unwrap(), "2");
+    assert_eq!(
+        std::fs::read_dir(to.join(DIR_2)).unwrap().count(),
+        1,
+        "Nothing should be left next to the destination"
+    );
+}
+
+#[test]
+fn test_mv_nested() {
+    let dir = PathBuf::from(&MV_NESTED);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&to).ok();
+    let mut deepest = from.clone();
+    for _ in 0..1000 {
+        deepest.push("d");
+    }
+    create_dir_all(&deepest).ok();
+    write(deepest.join(FILE_1), "deep").ok();
+
+    let options = rfm::CopyOptions::new().filter(rfm::Filter::new().include("*.txt"));
+    rfm::mv_with(vec![&from], &to, &options).unwrap();
+    let moved = to.join(deepest.strip_prefix(&dir).unwrap()).join(FILE_1);
+    assert_eq!(
+        read_to_string(moved).unwrap(),
+        "deep",
+        "Deep tree should be moved without recursion"
+    );
+
+    let inner = from.join(DIR_BAR);
+    remove_dir_all(&from).ok();
+    create_dir_all(inner.join(DIR_1)).ok();
+    write(inner.join(DIR_1).join(FILE_1), "inner").ok();
+    let err = rfm::mv(vec![&from], &inner).unwrap_err();
+    assert!(
+        matches!(err, rfm::Error::DestinationExists(_)),
+        "Directory should not be moved into itself"
+    );
+    assert_eq!(
+        read_to_string(inner.join(DIR_1).join(FILE_1)).unwrap(),
+        "inner"
+    );
+    remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_progress() {
+    let dir = PathBuf::from(&PROGRESS);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    for d in [&dir, &from, &from.join(DIR_3), &to] {
+        create_dir(d).ok();
+    }
+    write(from.join(FILE_1), "12345").ok();
+    write(from.join(DIR_3).join(FILE_2), "123").ok();
+
+    // (total bytes, total entries, copied bytes, finished entries, errors)
+    let stats = Arc::new(Mutex::new((0, 0, 0, 0, 0)));
+    let shared = stats.clone();
+    let progress = rfm::Progress::new(move |event| {
+        let mut stats = shared.lock().unwrap();
+        match event {
+            rfm::Event::Started {
+                total_bytes,
+                total_entries,
+            } => {
+                stats.0 = total_bytes.unwrap();
+                stats.1 = total_entries.unwrap();
+            }
+            rfm::Event::BytesCopied(n) => stats.2 += n,
+            rfm::Event::EntryFinished(_) => stats.3 += 1,
+            rfm::Event::Error(path, _) => {
+                assert!(path.ends_with("missing"));
+                stats.4 += 1;
+            }
+            rfm::Event::EntryStarted(_) | rfm::Event::FileCopied(..) => {}
+        }
+    })
+    .prescan(true);
+
+    let options = rfm::CopyOptions::new().progress(progress.clone());
+    rfm::cp_with(vec![&from], &to, &options).unwrap();
+    assert_eq!(
+        *stats.lock().unwrap(),
+        (8, 4, 8, 4, 0),
+        "Progress of cp should match the prescan"
+    );
+
+    *stats.lock().unwrap() = (0, 0, 0, 0, 0);
+    let options = rfm::RemoveOptions::new().progress(progress.clone());
+    rfm::rm_with(vec![&to.join(DIR_1)], &options).unwrap();
+    assert_eq!(
+        *stats.lock().unwrap(),
+        (8, 4, 0, 4, 0),
+        "Progress of rm should report entries only"
+    );
+
+    let missing = dir.join("missing");
+    let options = rfm::CopyOptions::new().progress(progress);
+    assert!(rfm::cp_with(vec![&missing], &to, &options).is_err());
+    assert_eq!(stats.lock().unwrap().4, 1, "Error should be reported");
+}
+
+#[test]
+fn test_cancel() {
+    let dir = PathBuf::from(&CANCEL);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    for d in [&dir, &from, &to] {
+        create_dir(d).ok();
+    }
+    write(from.join(FILE_1), "12345").ok();
+    write(from.join(FILE_2), "123").ok();
+
+    // Cancels the operation as soon as the first entry is finished.
+    let token = rfm::CancelToken::new();
+    let cancel = token.clone();
+    let progress = rfm::Progress::new(move |event| {
+        if let rfm::Event::EntryFinished(_) = event {
+            cancel.cancel();
+        }
+    });
+
+    let options = rfm::CopyOptions::new()
+        .progress(progress.clone())
+        .cancel(token.clone());
+    let err = rfm::cp_with(vec![&from], &to, &options).unwrap_err();
+    assert!(
+        matches!(err, rfm::Error::Cancelled(_)),
+        "cp should be cancelled"
+    );
+    assert_eq!(
+        err.summary().map(|s| s.entries()),
+        Some(1),
+        "Only the root directory should be copied"
+    );
+    assert_eq!(rfm::ls(to.join(DIR_1)).unwrap().len(), 0);
+
+    let token = rfm::CancelToken::new();
+    let options = rfm::RemoveOptions::new()
+        .progress(progress)
+        .cancel(token.clone());
+    token.cancel();
+    let err = rfm::rm_with(vec![&from], &options).unwrap_err();
+    assert!(
+        matches!(err, rfm::Error::Cancelled(_)),
+        "rm should be cancelled"
+    );
+    assert!(
+        from.join(FILE_1).exists() && from.join(FILE_2).exists(),
+        "Nothing should be deleted after cancelling"
+    );
+}
+
+#[test]
+fn test_plan() {
+    let dir = PathBuf::from(&PLAN);
+    let from = dir.join(DIR_1);
+    let to = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    for d in [&dir, &from, &from.join(DIR_3), &to, &to.join(DIR_1)] {
+        create_dir(d).ok();
+    }
+    write(from.join(FILE_1), "12345").ok();
+    write(from.join(DIR_3).join(FILE_2), "123").ok();
+    write(to.join(DIR_1).join(FILE_1), "old").ok();
+
+    let plan = rfm::Plan::cp(vec![&from], &to).unwrap();
+    assert_eq!(
+        plan.actions().len(),
+        3,
+        "dir-3, file-2 and file-1: {}",
+        plan
+    );
+    assert_eq!(
+        plan.conflicts().len(),
+        2,
+        "dir-1 and file-1 exist: {}",
+        plan
+    );
+    assert!(plan.actions().contains(&rfm::Action::Copy {
+        from: from.join(FILE_1),
+        to: to.join(DIR_1).join(FILE_1),
+        size: 5,
+    }));
+    assert!(
+        !to.join(DIR_1).join(DIR_3).exists(),
+        "Planning should not change anything"
+    );
+
+    plan.execute().unwrap();
+    assert_eq!(
+        read_to_string(to.join(DIR_1).join(DIR_3).join(FILE_2)).unwrap(),
+        "123"
+    );
+    assert_eq!(
+        read_to_string(to.join(DIR_1).join(FILE_1)).unwrap(),
+        "12345"
+    );
+
+    let plan = rfm::Plan::mv(vec![&from], dir.join(DIR_3)).unwrap_err();
+    assert!(
+        matches!(plan, rfm::Error::NotFound(_)),
+        "Missing destination"
+    );
+    let moved = dir.join(DIR_3);
+    create_dir(&moved).ok();
+    let plan = rfm::Plan::mv(vec![&from], &moved).unwrap();
+    assert_eq!(
+        plan.actions(),
+        &[rfm::Action::Rename {
+            from: from.clone(),
+            to: moved.join(DIR_1),
+            size: 8,
+        }]
+    );
+    plan.execute().unwrap();
+    assert!(!from.exists() && moved.join(DIR_1).join(FILE_1).exists());
+
+    let plan = rfm::Plan::rm(vec![&to]).unwrap();
+    assert_eq!(plan.actions().len(), 5, "Every entry is deleted: {}", plan);
+    plan.execute().unwrap();
+    assert!(!to.exists(), "Planned rm should delete the directory");
+
+    // Planned mkdir and touch follow their options.
+    let file = dir.join(FILE_1);
+    let nested = dir.join(DIR_1).join(DIR_FOO);
+    write(&file, "content").ok();
+    let exist_ok = rfm::MkdirOptions::new().exist_ok(true);
+    assert!(rfm::Plan::mkdir_with([&dir], &exist_ok).unwrap().is_empty());
+    let strict = rfm::MkdirOptions::new().parents(false);
+    assert!(matches!(
+        rfm::Plan::mkdir_with([&nested], &strict),
+        Err(rfm::Error::NotFound(_))
+    ));
+    let plan = rfm::Plan::mkdir_with([&nested], &exist_ok.mode(0o700)).unwrap();
+    assert_eq!(
+        plan.actions(),
+        &[
+            rfm::Action::CreateDir {
+                path: dir.join(DIR_1),
+                mode: None,
+            },
+            rfm::Action::CreateDir {
+                path: nested.clone(),
+                mode: Some(0o700),
+            },
+        ]
+    );
+    let keep = rfm::TouchOptions::new().truncate(false);
+    let plan = rfm::Plan::touch_with([&file], &keep).unwrap();
+    assert!(plan.conflicts().is_empty(), "Nothing is overwritten");
+    plan.execute().unwrap();
+    assert_eq!(read_to_string(&file).unwrap(), "content");
+
+    // A planned extract fails like the extract itself.
+    let missing = dir.join(DIR_2);
+    let planned = rfm::Plan::extract([&moved], &missing).unwrap_err();
+    let error = rfm::extract([&moved], &missing).unwrap_err();
+    assert!(matches!(planned, rfm::Error::NotFound(_)));
+    assert!(matches!(error, rfm::Error::NotFound(_)));
+    assert_eq!(planned.from_path(), error.from_path());
+    assert_eq!(planned.to_path(), error.to_path());
+    // An empty source has nothing to put at the missing destination.
+    let empty = dir.join(DIR_FOO);
+    create_dir(&empty).ok();
+    assert!(rfm::Plan::extract([&empty], &missing).unwrap().is_empty());
+    rfm::extract([&empty], &missing).unwrap();
+
+    let logs = dir.join(DIR_BAR);
+    create_dir(&logs).ok();
+    rfm::cp([&file], &logs).ok();
+    write(logs.join(FILE_2), "new").ok();
+    let options = rfm::PruneOptions::new()
+        .max_count(1)
+        .sort(rfm::SortBy::Name);
+    let plan = rfm::Plan::prune(&logs, &options).unwrap();
+    assert_eq!(
+        plan.actions(),
+        &[rfm::Action::Delete {
+            path: logs.join(FILE_1),
+            size: 7,
+        }]
+    );
+    assert!(
+        logs.join(FILE_1).exists(),
+        "Planning should not change anything"
+    );
+    plan.execute().unwrap();
+    assert!(!logs.join(FILE_1).exists());
+
+    #[cfg(target_os = "linux")]
+    {
+        let plan = rfm::Plan::trash([&moved]).unwrap();
+        assert_eq!(
+            plan.actions(),
+            &[rfm::Action::Trash {
+                path: moved.clone(),
+                size: 8,
+            }]
+        );
+        assert!(matches!(
+            rfm::Plan::trash([&missing]),
+            Err(rfm::Error::NotFound(_))
+        ));
+    }
+}
+
+#[test]
+fn test_guard() {
+    let dir = PathBuf::from(GUARD);
+    let dir_1 = dir.join(DIR_1);
+    let keep = dir_1.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&dir_1).ok();
+    rfm::cp([PathBuf::from(DATA).join(DIR_2)], &dir_1).ok();
+
+    let root = PathBuf::from("/");
+    assert!(
+        matches!(rfm::Plan::rm([&root]), Err(rfm::Error::Protected(_))),
+        "The root should be refused"
+    );
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        let parent = home.join("..");
+        assert!(
+            matches!(rfm::Plan::clean([&parent]), Err(rfm::Error::Protected(_))),
+            "The parent of the home directory should be refused"
+        );
+    }
+
+    // `dir-1/dir-2/..` is `dir-1`, which contains the protected directory.
+    let sneaky = keep.join("..");
+    let options = rfm::RemoveOptions::new().guard(rfm::Guard::new().protect(&keep));
+    assert!(matches!(
+        rfm::rm_with([&sneaky], &options),
+        Err(rfm::Error::Protected(_))
+    ));
+    let options = rfm::CopyOptions::new().guard(rfm::Guard::new().protect(&keep));
+    assert!(matches!(
+        rfm::mv_with([&dir_1], &dir, &options),
+        Err(rfm::Error::Protected(_))
+    ));
+    assert!(keep.join(FILE_1).exists(), "Nothing should be touched");
+
+    let options = rfm::CleanOptions::new().guard(rfm::Guard::disabled());
+    rfm::clean_with([&dir_1], &options).unwrap();
+    assert!(
+        dir_1.exists() && !keep.exists(),
+        "The override should allow it"
+    );
+}
+
+#[test]
+fn test_clean_with() {
+    let dir = PathBuf::from(CLEAN_WITH);
+    let dir_1 = dir.join(DIR_1);
+    let dir_2 = dir.join(DIR_2);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    rfm::cp(
+        [DIR_1, DIR_2, FILE_1]
+            .iter()
+            .map(|name| PathBuf::from(DATA).join(name)),
+        &dir,
+    )
+    .ok();
+    write(dir.join(FILE_2), "new").ok();
+    File::create(dir.join(".gitkeep")).ok();
+    for path in [
+        dir.join(FILE_1),
+        dir.join(".gitkeep"),
+        dir_1.join(FILE_1),
+        dir_1.join(FILE_2),
+        dir_2.join(FILE_1),
+        dir_1.clone(),
+    ] {
+        backdate(&path, 60 * 60);
+    }
+
+    let options = rfm::CleanOptions::new()
+        .keep(".gitkeep")
+        .older_than(Duration::from_secs(60));
+    let plan = rfm::Plan::clean_with([&dir], &options).unwrap();
+    assert_eq!(
+        plan.actions().len(),
+        5,
+        "Old files and the old emptied dir-1: {}",
+        plan
+    );
+
+    #[cfg(unix)]
+    let ino = std::os::unix::fs::MetadataExt::ino(&metadata(&dir).unwrap());
+    rfm::clean_with([&dir], &options).unwrap();
+    assert!(!dir.join(FILE_1).exists() && !dir_1.exists());
+    assert!(
+        dir.join(FILE_2).exists() && dir.join(".gitkeep").exists(),
+        "New and kept files should stay"
+    );
+    assert!(
+        dir_2.join(DIR_FOO).exists() && !dir_2.join(FILE_1).exists(),
+        "A new directory should stay without its old files"
+    );
+
+    rfm::clean([&dir]).unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    #[cfg(unix)]
+    assert_eq!(
+        std::os::unix::fs::MetadataExt::ino(&metadata(&dir).unwrap()),
+        ino,
+        "The cleaned directory should be the same"
+    );
+}
+
+#[test]
+fn test_prune() {
+    let dir = PathBuf::from(PRUNE);
+    let names = ["a.log", "b.log", "c.log", "d.log"];
+    let logs: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+    let options = rfm::PruneOptions::new().filter(rfm::Filter::new().include("*.log"));
+
+    // Four logs from `a.log`, the oldest and smallest, to `d.log`, the
+    // newest and largest, and a file that isn't a log.
+    let setup = || {
+        remove_dir_all(&dir).ok();
+        create_dir(&dir).ok();
+        rfm::cp([PathBuf::from(DATA).join(FILE_1)], &dir).ok();
+        for (i, log) in logs.iter().enumerate() {
+            write(log, "x".repeat(i + 1)).ok();
+            backdate(log, 60 * 60 * (4 - i) as u64);
+        }
+    };
+
+    setup();
+    let removed = rfm::prune(&dir, &options.clone().max_count(3)).unwrap();
+    assert_eq!(removed, [logs[0].clone()]);
+
+    // d.log (4 bytes) fits, c.log (3 bytes) doesn't, the older ones follow it.
+    setup();
+    let removed = rfm::prune(&dir, &options.clone().max_size(5)).unwrap();
+    assert_eq!(
+        removed,
+        logs[..3],
+        "The oldest entries should be deleted first"
+    );
+
+    setup();
+    let options = options
+        .max_age(Duration::from_secs(90 * 60))
+        .sort(rfm::SortBy::Name);
+    let removed = rfm::prune(&dir, &options).unwrap();
+    assert_eq!(removed, logs[..3]);
+    assert!(
+        dir.join(FILE_1).exists(),
+        "Excluded entries should not be pruned"
+    );
+}
+
+#[test]
+fn test_extract_naming() {
+    let dir = PathBuf::from(NAMING);
+    let from = dir.join(DIR_2);
+    let to = dir.join("to");
+
+    // `dir-2` has `file-1.txt` three times, one of them with other content.
+    let extract = |naming: rfm::Naming| -> rfm::Result<Vec<String>> {
+        remove_dir_all(&dir).ok();
+        create_dir(&dir).ok();
+        create_dir(&to).ok();
+        rfm::cp([PathBuf::from(DATA).join(DIR_2)], &dir).ok();
+        write(from.join(DIR_FOO).join(FILE_1), "foo").ok();
+
+        let options = rfm::ExtractOptions::new().naming(naming);
+        rfm::extract_with([&from], &to, &options)?;
+        let mut names: Vec<String> = rfm::ls(&to)?
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        Ok(names)
+    };
+
+    assert!(matches!(
+        extract(rfm::Naming::Error),
+        Err(rfm::Error::DestinationExists(_))
+    ));
+    assert_eq!(
+        extract(rfm::Naming::Numbered).unwrap(),
+        ["file-1 (2).txt", "file-1 (3).txt", "file-1.txt"]
+    );
+    assert_eq!(
+        extract(rfm::Naming::PathEncoded).unwrap(),
+        ["file-1.txt", "foo__bar__file-1.txt", "foo__file-1.txt"]
+    );
+
+    let names = extract(rfm::Naming::Dedupe).unwrap();
+    assert_eq!(names.len(), 2, "Duplicates should collapse: {:?}", names);
+    let mut contents: Vec<String> = names
+        .iter()
+        .map(|name| read_to_string(to.join(name)).unwrap())
+        .collect();
+    contents.sort();
+    assert_eq!(contents, ["", "foo"]);
+
+    // `foo__bar/file-1.txt` is encoded like `foo/bar__file-1.txt`.
+    let encoded = from.join("foo__bar");
+    create_dir(&encoded).ok();
+    rfm::rm([&to]).ok();
+    create_dir(&to).ok();
+    rfm::cp([from.join(DIR_FOO).join(FILE_1)], &encoded).ok();
+    rfm::rm([from.join(FILE_1), from.join(DIR_FOO).join(FILE_1)]).ok();
+    let options = rfm::ExtractOptions::new().naming(rfm::Naming::PathEncoded);
+    assert!(
+        matches!(
+            rfm::extract_with([&from], &to, &options),
+            Err(rfm::Error::DestinationExists(_))
+        ),
+        "Paths encoded to the same name should not overwrite each other"
+    );
+    assert_eq!(rfm::ls(&to).unwrap().len(), 1);
+}
+
+#[test]
+fn test_extract_move() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(EXTRACT_MOVE);
+    let to = dir.join("to");
+
+    let setup = |name: &str| -> PathBuf {
+        remove_dir_all(&dir).ok();
+        create_dir(&dir).ok();
+        create_dir(&to).ok();
+        rfm::cp([data_dir.join(name)], &dir).ok();
+        dir.join(name)
+    };
+
+    // Two files of `dir-2` are duplicates, the third has other content.
+    let from = setup(DIR_2);
+    write(from.join(DIR_FOO).join(FILE_1), "foo").ok();
+    let options = rfm::ExtractOptions::new()
+        .naming(rfm::Naming::Dedupe)
+        .move_files(true)
+        .remove_root(true);
+
+    let plan = rfm::Plan::extract_with(vec![&from], &to, &options).unwrap();
+    assert_eq!(
+        plan.actions().len(),
+        6,
+        "Two moves, a duplicate and three directories: {}",
+        plan
+    );
+    rfm::extract_with(vec![&from], &to, &options).unwrap();
+    assert!(!from.exists(), "The emptied source should be deleted");
+    assert_eq!(rfm::ls(&to).unwrap().len(), 2);
+
+    // A skipped file keeps its directories in the source.
+    let from = setup(DIR_3);
+    write(to.join("file-3.txt"), "old").ok();
+    let options = rfm::ExtractOptions::new()
+        .naming(rfm::Naming::Numbered)
+        .conflict(rfm::ConflictPolicy::Skip)
+        .move_files(true)
+        .remove_root(true);
+
+    rfm::extract_with(vec![&from], &to, &options).unwrap();
+    let subdir_2 = from.join("subdir-1").join("subdir-2");
+    assert!(
+        !from.join(FILE_1).exists() && !subdir_2.join("file-4.txt").exists(),
+        "The other files should be moved"
+    );
+    assert!(
+        subdir_2.join("file-3.txt").exists(),
+        "The skipped file should stay"
+    );
+    assert_eq!(read_to_string(to.join("file-3.txt")).unwrap(), "old");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_usage() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(USAGE);
+    let sparse = dir.join(DIR_1).join("sparse");
+    let len = metadata(data_dir.join(FILE_1)).unwrap().len();
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(dir.join(DIR_1)).ok();
+    rfm::cp([data_dir.join(FILE_1)], &dir).ok();
+    std::fs::hard_link(dir.join(FILE_1), dir.join(FILE_2)).ok();
+    std::os::unix::fs::symlink(FILE_1, dir.join("link")).ok();
+    File::create(&sparse).unwrap().set_len(1024 * 1024).ok();
+
+    let apparent = rfm::SizeOptions::new().mode(rfm::SizeMode::Apparent);
+    let usage = rfm::get_usage_with(&dir, &apparent).unwrap();
+    assert_eq!(
+        usage.bytes(),
+        2 * len + 1024 * 1024,
+        "Only files are counted"
+    );
+    assert_eq!(
+        (usage.files(), usage.dirs(), usage.symlinks()),
+        (3, 1, 1),
+        "Entries should be counted by type"
+    );
+
+    let usage = rfm::get_usage_with(&dir, &apparent.dedupe_hard_links(true)).unwrap();
+    assert_eq!(usage.bytes(), len + 1024 * 1024, "Hard links count once");
+    assert_eq!(usage.files(), 2);
+
+    let disk = rfm::SizeOptions::new().mode(rfm::SizeMode::DiskUsage);
+    assert!(
+        rfm::get_size_with(&sparse, &disk).unwrap() < 1024 * 1024,
+        "A sparse file should use less space than its length"
+    );
+}
+
+#[test]
+fn test_du() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(DU);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    let entries = [FILE_1, DIR_1, DIR_2, DIR_3];
+    rfm::cp(entries.iter().map(|name| data_dir.join(name)), &dir).ok();
+    write(dir.join(DIR_3).join("subdir-1").join(FILE_2), "0123456789").ok();
+    let options = rfm::SizeOptions::new().mode(rfm::SizeMode::Apparent);
+
+    let mut tree = rfm::du_with(&dir, 1, &options).unwrap();
+    assert_eq!((tree.size(), tree.file_count()), (16, 10));
+    assert_eq!(tree.children().len(), 3, "Only directories are nodes");
+    assert!(
+        tree.children()
+            .iter()
+            .all(|child| child.children().is_empty()),
+        "Nodes below the depth should not be returned"
+    );
+
+    tree.sort_by_size();
+    let largest = &tree.children()[0];
+    assert_eq!(largest.path(), dir.join(DIR_3));
+    assert_eq!((largest.size(), largest.file_count()), (10, 4));
+
+    let tree = rfm::du_with(&dir, 2, &options).unwrap();
+    assert_eq!(tree.nodes().len(), 6, "Nodes should stop at the depth");
+    assert_eq!(
+        rfm::du(&dir, 0).unwrap().size(),
+        rfm::get_size(&dir).unwrap()
+    );
+}
+
+#[test]
+fn test_parallel() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(PARALLEL);
+    let from = dir.join(DIR_1);
+    let subtree = |i: usize| from.join(format!("{}-{}", DIR_FOO, i));
+
+    // Wide enough for several threads: 8 copies of `dir-3`.
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    for i in 0..8 {
+        create_dir_all(subtree(i)).ok();
+        rfm::cp([data_dir.join(DIR_3)], subtree(i)).ok();
+    }
+    backdate(&subtree(0), 60 * 60);
+
+    let usage = rfm::get_usage_with(&from, &rfm::SizeOptions::new().threads(4)).unwrap();
+    assert_eq!(usage, rfm::get_usage(&from).unwrap());
+    assert_eq!((usage.files(), usage.dirs()), (32, 32));
+
+    let to = dir.join(DIR_2);
+    create_dir(&to).ok();
+    let options = rfm::CopyOptions::new()
+        .threads(4)
+        .preserve(rfm::Preserve::all());
+    rfm::cp_with(vec![&from], &to, &options).unwrap();
+    let copied = to.join(DIR_1);
+    assert_eq!(
+        rfm::get_usage(&copied).unwrap(),
+        rfm::get_usage(&from).unwrap()
+    );
+    let modified = |d: &Path| {
+        metadata(d.join(format!("{}-0", DIR_FOO)))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    assert_eq!(
+        modified(&copied),
+        modified(&from),
+        "Directory metadata should be preserved after its content"
+    );
+
+    let to = dir.join(DIR_3);
+    create_dir(&to).ok();
+    let options = rfm::ExtractOptions::new()
+        .threads(4)
+        .naming(rfm::Naming::Numbered);
+    rfm::extract_with(vec![&from], &to, &options).unwrap();
+    assert_eq!(rfm::ls(&to).unwrap().len(), 32);
+
+    rfm::rm_with(vec![&from], &rfm::RemoveOptions::new().threads(0)).unwrap();
+    assert!(!from.exists(), "Dir should be removed");
+}
+
+#[test]
+fn test_paths() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(PATHS);
+    let file = format!("{}/{}", PATHS, FILE_2);
+    let sub = dir.join(DIR_1);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+
+    // Strings and path buffers
+    rfm::touch([file.as_str()]).unwrap();
+    rfm::mkdir(vec![sub.clone()]).unwrap();
+    assert_eq!(rfm::ls(dir.to_str().unwrap()).unwrap().len(), 2);
+    rfm::cp([&file], sub.to_str().unwrap()).unwrap();
+    assert_eq!(rfm::get_size(sub.join(FILE_2)).unwrap(), 0);
+
+    // Options and iterators
+    rfm::cp(Some(data_dir.join(FILE_1)), &sub).unwrap();
+    assert!(sub.join(FILE_1).is_file(), "File should be copied");
+    let names = [DIR_1, FILE_2];
+    rfm::rm(names.iter().map(|name| dir.join(name))).unwrap();
+    assert!(rfm::ls(&dir).unwrap().is_empty(), "Dir should be empty");
+
+    assert!(matches!(
+        rfm::rm(Vec::<PathBuf>::new()),
+        Err(rfm::Error::EmptyInput(_))
+    ));
+}
+
+#[test]
+fn test_mkdir_with() {
+    let dir = PathBuf::from(MKDIR_WITH);
+    let nested = dir.join(DIR_1).join(DIR_FOO);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+
+    let strict = rfm::MkdirOptions::new().parents(false);
+    assert!(
+        rfm::mkdir_with([&nested], &strict).is_err(),
+        "Parents should not be created"
+    );
+    assert!(matches!(
+        rfm::mkdir([&dir]),
+        Err(rfm::Error::DestinationExists(_))
+    ));
+    let options = rfm::MkdirOptions::new().exist_ok(true);
+    rfm::mkdir_with([&dir, &nested], &options).unwrap();
+    assert!(nested.is_dir(), "Dir should be created with its parents");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let private = dir.join(DIR_2);
+        rfm::mkdir_with([&private], &rfm::MkdirOptions::new().mode(0o700)).unwrap();
+        let mode = metadata(&private).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700, "Mode should be applied");
+    }
+}
+
+#[test]
+fn test_touch_with() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(TOUCH_WITH);
+    let file = dir.join(DIR_2).join(FILE_1);
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+
+    assert!(rfm::touch([&file]).is_err(), "Parent should be missing");
+    let options = rfm::TouchOptions::new().parents(true);
+    rfm::touch_with([&file], &options).unwrap();
+    assert!(file.is_file(), "File should be created with its parent");
+
+    rfm::cp([data_dir.join(FILE_1)], dir.join(DIR_2)).ok();
+    let content = read_to_string(&file).unwrap();
+    assert!(
+        !content.is_empty(),
+        "File should be overwritten by the copy"
+    );
+    rfm::touch_with([&file], &options.truncate(false)).unwrap();
+    assert_eq!(read_to_string(&file).unwrap(), content);
+    rfm::touch([&file]).unwrap();
+    assert_eq!(
+        read_to_string(&file).unwrap(),
+        "",
+        "File should be truncated"
+    );
+}
+
+#[test]
+fn test_batch() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(BATCH);
+    let file_1 = dir.join(FILE_1);
+    let file_2 = dir.join(FILE_2);
+    let (dir_1, dir_2) = (dir.join(DIR_1), dir.join(DIR_2));
+    let missing = dir.join(DIR_3).join(FILE_1);
+
+    let setup = || {
+        remove_dir_all(&dir).ok();
+        create_dir(&dir).ok();
+        rfm::cp([data_dir.join(DIR_1), data_dir.join(DIR_2)], &dir).ok();
+    };
+
+    // Strict mode stops at the first error
+    setup();
+    let error = rfm::touch([&file_1, &missing, &file_2]).unwrap_err();
+    assert!(matches!(error, rfm::Error::NotFound(_)));
+    assert!(file_1.exists());
+    assert!(!file_2.exists(), "Strict mode should stop");
+
+    // Continue mode reports every path
+    setup();
+    let options = rfm::TouchOptions::new().on_error(rfm::ErrorMode::Continue);
+    let error = rfm::touch_with([&file_1, &missing, &file_2], &options).unwrap_err();
+    assert!(file_2.exists(), "Continue mode should process every path");
+    let report = error.batch().expect("Batch error should carry a report");
+    assert_eq!(report.succeeded().collect::<Vec<_>>(), [&file_1, &file_2]);
+    assert_eq!(report.failed().count(), 1);
+    assert!(matches!(
+        report.error(&missing),
+        Some(rfm::Error::NotFound(_))
+    ));
+
+    rfm::rm([dir_1.join(FILE_1)]).ok();
+    let options = rfm::CopyOptions::new().on_error(rfm::ErrorMode::Continue);
+    let error = rfm::cp_with([&missing, &file_1], &dir_1, &options).unwrap_err();
+    assert!(matches!(error, rfm::Error::Batch(_)));
+    assert!(dir_1.join(FILE_1).exists(), "File should be copied");
+
+    let options = rfm::RemoveOptions::new().on_error(rfm::ErrorMode::Continue);
+    let error = rfm::rm_with([&missing, &dir_1], &options).unwrap_err();
+    assert_eq!(error.batch().unwrap().failed().count(), 1);
+    assert!(!dir_1.exists(), "Dir should be removed");
+
+    // Guard failures
+    setup();
+    let guard = rfm::Guard::new().protect(&dir_1);
+    let strict = rfm::RemoveOptions::new().guard(guard.clone());
+    assert!(matches!(
+        rfm::rm_with([&dir_2, &dir_1], &strict),
+        Err(rfm::Error::Protected(_))
+    ));
+    assert!(dir_2.exists(), "Strict mode should check every path first");
+
+    let options = rfm::CleanOptions::new()
+        .guard(guard.clone())
+        .on_error(rfm::ErrorMode::Continue);
+    let error = rfm::clean_with([&dir_1, &dir_2], &options).unwrap_err();
+    let report = error
+        .batch()
+        .expect("Guard failures should be in the report");
+    assert!(matches!(
+        report.error(&dir_1),
+        Some(rfm::Error::Protected(_))
+    ));
+    assert!(dir_1.join(FILE_1).exists());
+    assert!(
+        !dir_2.join(FILE_1).exists(),
+        "Other paths should be cleaned"
+    );
+
+    let options = rfm::RemoveOptions::new()
+        .guard(guard)
+        .on_error(rfm::ErrorMode::Continue);
+    let error = rfm::rm_with([&dir_1, &dir_2], &options).unwrap_err();
+    assert_eq!(
+        error.batch().unwrap().succeeded().collect::<Vec<_>>(),
+        [&dir_2]
+    );
+    assert!(dir_1.exists() && !dir_2.exists());
+}
+
+#[test]
+fn test_target_name() {
+    let data_dir = PathBuf::from(DATA);
+    let dir = PathBuf::from(TARGET);
+    let file_1 = dir.join(FILE_1);
+    let file_2 = dir.join(FILE_2);
+    let dir_1 = dir.join(DIR_1);
+    let content = read_to_string(data_dir.join(FILE_1)).unwrap();
+
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    rfm::cp([data_dir.join(FILE_1), data_dir.join(DIR_1)], &dir).ok();
+
+    let as_name = rfm::CopyOptions::new().no_target_directory(true);
+    rfm::cp_with([&file_1], &file_2, &as_name).unwrap();
+    assert_eq!(read_to_string(&file_2).unwrap(), content);
+
+    // Parents of the target are only created when asked
+    let backup = dir.join(DIR_2).join(DIR_BAR);
+    let error = rfm::cp_with([&dir_1], &backup, &as_name).unwrap_err();
+    assert!(matches!(error, rfm::Error::NotFound(_)));
+    assert!(!dir.join(DIR_2).exists(), "Nothing should be created");
+    rfm::cp_with([&dir_1], &backup, &as_name.clone().create_target(true)).unwrap();
+    assert!(
+        backup.join(FILE_2).is_file(),
+        "Dir should be copied as name"
+    );
+
+    let error = rfm::cp_with([&file_1, &dir_1], dir.join(DIR_3), &as_name).unwrap_err();
+    assert!(matches!(error, rfm::Error::TooManyPaths(_)));
+
+    let plan = rfm::Plan::mv_with(
+        [&file_2],
+        dir.join(DIR_3).join(FILE_2),
+        &as_name.clone().create_target(true),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.actions().len(),
+        2,
+        "Plan should create the missing parent"
+    );
+    let moved = dir_1.join("file-3.txt");
+    rfm::mv_with([&file_2], &moved, &as_name).unwrap();
+    assert!(!file_2.exists());
+    assert_eq!(read_to_string(&moved).unwrap(), content);
+
+    // Target directories
+    let into = rfm::CopyOptions::new().create_target(true);
+    rfm::cp_with([&file_1], dir.join(DIR_3), &into).unwrap();
+    assert!(dir.join(DIR_3).join(FILE_1).is_file());
+    assert!(matches!(
+        rfm::cp([&file_1], &file_1),
+        Err(rfm::Error::NotADirectory(_))
+    ));
+}
+
+#[test]
+fn test_reflink() {
+    let dir = PathBuf::from(REFLINK);
+    let from = dir.join(DIR_1);
+    let file_1 = from.join(FILE_1);
+    let file_2 = dir.join(FILE_2);
+
+    // A file spanning a few copy chunks
+    let content: Vec<u8> = (0..3 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+    create_dir(&from).ok();
+    create_dir(dir.join(DIR_2)).ok();
+    create_dir(dir.join(DIR_3)).ok();
+    write(&file_1, &content).ok();
+
+    let to = dir.join(DIR_2);
+    let (progress, stats) = copy_stats();
+    let never = rfm::CopyOptions::new()
+        .reflink(rfm::Reflink::Never)
+        .progress(progress);
+    rfm::cp_with([&from], &to, &never).unwrap();
+    assert_eq!(std::fs::read(to.join(DIR_1).join(FILE_1)).unwrap(), content);
+    let stats = stats.lock().unwrap();
+    assert_eq!(stats.0, content.len() as u64);
+    assert_eq!(stats.1.len(), 1, "Every file should report its method");
+    assert_ne!(stats.1[0], rfm::CopyMethod::Reflink);
+
+    let to = dir.join(DIR_3);
+    let (progress, stats) = copy_stats();
+    let auto = rfm::ExtractOptions::new().progress(progress);
+    rfm::extract_with([&from], &to, &auto).unwrap();
+    assert_eq!(std::fs::read(to.join(FILE_1)).unwrap(), content);
+    assert_eq!(stats.lock().unwrap().0, content.len() as u64);
+
+    // Reflinks depend on the filesystem, a failed clone leaves nothing.
+    let always = rfm::CopyOptions::new()
+        .reflink(rfm::Reflink::Always)
+        .no_target_directory(true);
+    match rfm::cp_with([&file_1], &file_2, &always) {
+        Ok(()) => assert_eq!(std::fs::read(&file_2).unwrap(), content),
+        Err(_) => assert!(!file_2.exists(), "Failed clone should be deleted"),
+    }
+}
+
+/// Copied bytes and the methods of the copied files.
+type CopyStats = Arc<Mutex<(u64, Vec<rfm::CopyMethod>)>>;
+
+/// Returns a progress callback collecting [`CopyStats`].
+fn copy_stats() -> (rfm::Progress, CopyStats) {
+    let stats = Arc::new(Mutex::new((0, vec![])));
+    let shared = stats.clone();
+    let progress = rfm::Progress::new(move |event| {
+        let mut stats = shared.lock().unwrap();
+        match event {
+            rfm::Event::BytesCopied(n) => stats.0 += n,
+            rfm::Event::FileCopied(_, method) => stats.1.push(*method),
+            _ => {}
+        }
+    });
+    (progress, stats)
+}
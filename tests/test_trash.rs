@@ -0,0 +1,104 @@
+//! The trash tests point `XDG_DATA_HOME` into the test directory. The
+//! environment is shared by the whole process, so they live in their own test
+//! binary, away from the tests that run in parallel with them.
+#![cfg(target_os = "linux")]
+extern crate rfm;
+use std::{
+    fs::{create_dir, read_to_string, remove_dir_all, write},
+    path::PathBuf,
+};
+
+static FILE_1: &str = "file-1.txt";
+static FILE_2: &str = "file-2.txt";
+static TRASH: &str = "./tests/testing/trash";
+
+#[test]
+fn test_trash() {
+    let dir = PathBuf::from(&TRASH);
+    remove_dir_all(&dir).ok();
+    create_dir(&dir).ok();
+
+    // The home trash is kept inside the test directory.
+    let data_home = dir.canonicalize().unwrap().join("data");
+    std::env::set_var("XDG_DATA_HOME", &data_home);
+
+    let file_1 = dir.join(FILE_1);
+    let dir_1 = dir.join("dir 1");
+    write(&file_1, "1").ok();
+    create_dir(&dir_1).ok();
+    write(dir_1.join(FILE_2), "2").ok();
+
+    rfm::trash(vec![&file_1, &dir_1]).unwrap();
+    assert!(
+        !file_1.exists() && !dir_1.exists(),
+        "Trashed items should be gone"
+    );
+    assert!(data_home.join("Trash/files").join(FILE_1).exists());
+
+    let info = read_to_string(data_home.join("Trash/info/dir 1.trashinfo")).unwrap();
+    assert!(
+        info.starts_with("[Trash Info]\nPath=/") && info.contains("dir%201\nDeletionDate="),
+        "Info file should follow the specification: {}",
+        info
+    );
+
+    // A second item with the same name gets another name in the trash.
+    write(&file_1, "new").ok();
+    rfm::trash(vec![&file_1]).unwrap();
+
+    // Only the home trash, the trashes on the real mounts are left alone.
+    let options = rfm::TrashOptions::new().home_only(true);
+    let abs_dir = dir.canonicalize().unwrap();
+    let items: Vec<rfm::TrashItem> = rfm::list_trash_with(&options)
+        .unwrap()
+        .into_iter()
+        .filter(|item| item.original_path().starts_with(&abs_dir))
+        .collect();
+    assert_eq!(items.len(), 3, "Every trashed item should be listed");
+
+    for item in items
+        .iter()
+        .filter(|i| i.original_path().ends_with("dir 1"))
+    {
+        rfm::restore(item).unwrap();
+    }
+    assert_eq!(read_to_string(dir_1.join(FILE_2)).unwrap(), "2");
+
+    let file_items: Vec<&rfm::TrashItem> = items
+        .iter()
+        .filter(|i| i.original_path().ends_with(FILE_1))
+        .collect();
+    rfm::restore(file_items[0]).unwrap();
+    assert!(
+        matches!(
+            rfm::restore(file_items[1]),
+            Err(rfm::Error::DestinationExists(_))
+        ),
+        "Restore should not overwrite existing files"
+    );
+
+    assert!(
+        matches!(rfm::trash(["/"]), Err(rfm::Error::Protected(_))),
+        "The root should not be trashed"
+    );
+    let guard = rfm::Guard::new().protect(&file_1);
+    assert!(matches!(
+        rfm::trash_with(vec![&file_1], &rfm::TrashOptions::new().guard(guard)),
+        Err(rfm::Error::Protected(_))
+    ));
+    assert!(file_1.exists(), "Protected file should stay");
+
+    rfm::empty_trash_with(&options).unwrap();
+    assert!(
+        rfm::list_trash_with(&options).unwrap().is_empty(),
+        "Emptied trash should have no items"
+    );
+    assert_eq!(
+        std::fs::read_dir(data_home.join("Trash/files"))
+            .unwrap()
+            .count(),
+        0,
+        "Trashed files should be deleted"
+    );
+    assert!(file_1.exists(), "Files outside the trash should stay");
+}